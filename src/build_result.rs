@@ -0,0 +1,76 @@
+//! What a build produced, for callers of [`ProjectEvaluator::execute_target`].
+//!
+//! Every target that was considered gets a [`TargetResult`] with its outcome and
+//! the items named by its `Returns` attribute, or by `Outputs` when it has none.
+//!
+//! [`ProjectEvaluator::execute_target`]: crate::evaluation::ProjectEvaluator::execute_target
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use std::fmt;
+
+use crate::object_model::Item;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TargetOutcome {
+    Success,
+    /// A task failed, or a target this one depends on did; holds the error
+    Failure(String),
+    /// The condition was false or the outputs were up to date; holds the reason
+    Skipped(String),
+}
+
+impl fmt::Display for TargetOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Success => write!(f, "succeeded"),
+            Self::Failure(error) => write!(f, "failed: {error}"),
+            Self::Skipped(reason) => write!(f, "skipped: {reason}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TargetResult {
+    pub outcome: TargetOutcome,
+    /// The returned items with their metadata; values that did not come from an
+    /// item reference have an empty item type
+    pub items: Vec<Item>,
+}
+
+#[derive(Debug, Default)]
+pub struct BuildResult {
+    /// Results by target name, in the order the targets finished
+    pub targets: IndexMap<String, TargetResult>,
+    error: Option<anyhow::Error>,
+}
+
+impl BuildResult {
+    pub fn add_target_result(&mut self, name: String, result: TargetResult) {
+        self.targets.insert(name, result);
+    }
+
+    #[allow(dead_code)] // Public API method for library users
+    pub fn get_target_result(&self, name: &str) -> Option<&TargetResult> {
+        self.targets.get(name)
+    }
+
+    /// Record the error that failed the build
+    pub fn set_error(&mut self, error: anyhow::Error) {
+        self.error = Some(error);
+    }
+
+    #[allow(dead_code)] // Public API method for library users
+    pub fn is_success(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Turn a failed build into its error, for callers that only need to know
+    /// whether it succeeded
+    pub fn into_result(mut self) -> Result<Self> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => Ok(self),
+        }
+    }
+}
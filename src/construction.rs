@@ -0,0 +1,859 @@
+//! Construction-level view of a project file.
+//!
+//! Where [`crate::object_model::ProjectModel`] holds the evaluated facts of a
+//! project, [`ProjectRootElement`] holds the XML exactly as it was written:
+//! comments, whitespace, attribute order and quoting, and elements nobody
+//! understands. Writing an unchanged tree back produces the original bytes.
+
+use anyhow::{Result, anyhow};
+use quick_xml::Reader;
+use quick_xml::escape::unescape;
+use quick_xml::events::Event;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use crate::encoding::{self, BOM, TextEncoding};
+use crate::file_system::{FileSystem, PhysicalFileSystem};
+use crate::object_model::SourceLocation;
+
+/// The namespace of MSBuild 2003-era projects; elements in it or in no namespace
+/// are MSBuild elements
+pub const MSBUILD_NAMESPACE: &str = "http://schemas.microsoft.com/developer/msbuild/2003";
+
+/// A node in the lossless XML tree
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    /// Character data exactly as written, entity references still escaped
+    Text(String),
+    /// Comment body, without the `<!--` and `-->` delimiters
+    Comment(String),
+    /// CDATA body, without the `<![CDATA[` and `]]>` delimiters
+    CData(String),
+    /// XML declarations, processing instructions and doctypes, verbatim
+    Markup(String),
+}
+
+/// An attribute together with the whitespace and quoting around it
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlAttribute {
+    leading: String,
+    pub name: String,
+    separator: String,
+    quote: char,
+    raw_value: String,
+}
+
+impl XmlAttribute {
+    /// The attribute value with entity references expanded
+    pub fn value(&self) -> String {
+        unescape_lossy(&self.raw_value)
+    }
+
+    /// `xmlns` and `xmlns:prefix` declare namespaces rather than carry data
+    pub fn is_namespace_declaration(&self) -> bool {
+        self.name == "xmlns" || self.name.starts_with("xmlns:")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlElement {
+    pub name: String,
+    pub attributes: Vec<XmlAttribute>,
+    pub children: Vec<XmlNode>,
+    /// Written as `<Name />` rather than `<Name></Name>`
+    pub self_closing: bool,
+    /// Whitespace between the last attribute and `>` or `/>`
+    tag_trailer: String,
+    /// The closing tag verbatim, empty for self-closing elements
+    end_tag: String,
+    /// The resolved namespace URI of the element, if it is in one
+    pub namespace: Option<String>,
+    pub location: SourceLocation,
+}
+
+/// Indentation unit and line ending of a document, used to lay out inserted elements
+#[derive(Debug, Clone)]
+pub struct Layout {
+    pub indent: String,
+    pub newline: String,
+}
+
+impl XmlElement {
+    /// A new self-closing element, ready to be inserted with [`XmlElement::insert_child`]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+            self_closing: true,
+            tag_trailer: " ".to_string(),
+            end_tag: String::new(),
+            namespace: None,
+            location: SourceLocation::default(),
+        }
+    }
+
+    /// The element name without its namespace prefix
+    pub fn local_name(&self) -> &str {
+        self.name
+            .split_once(':')
+            .map_or(self.name.as_str(), |(_, local)| local)
+    }
+
+    /// The local name if this is an MSBuild element, i.e. in the MSBuild namespace
+    /// or in none; `None` for elements that belong to some other vocabulary
+    pub fn msbuild_name(&self) -> Option<&str> {
+        match self.namespace.as_deref() {
+            None | Some(MSBUILD_NAMESPACE) => Some(self.local_name()),
+            Some(_) => None,
+        }
+    }
+
+    /// Child elements in the MSBuild vocabulary, paired with their local names
+    pub fn msbuild_children(&self) -> impl Iterator<Item = (&str, &XmlElement)> {
+        self.child_elements()
+            .filter_map(|child| child.msbuild_name().map(|name| (name, child)))
+    }
+
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.set_attribute(name, value);
+        self
+    }
+
+    /// Update an attribute in place, keeping its quoting, or append a new one
+    pub fn set_attribute(&mut self, name: &str, value: &str) {
+        if let Some(attr) = self.attributes.iter_mut().find(|attr| attr.name == name) {
+            attr.raw_value = escape_attribute(value, attr.quote);
+            return;
+        }
+        self.attributes.push(XmlAttribute {
+            leading: " ".to_string(),
+            name: name.to_string(),
+            separator: "=".to_string(),
+            quote: '"',
+            raw_value: escape_attribute(value, '"'),
+        });
+    }
+
+    #[allow(dead_code)] // Public API method for library users
+    pub fn remove_attribute(&mut self, name: &str) -> bool {
+        let count = self.attributes.len();
+        self.attributes.retain(|attr| attr.name != name);
+        self.attributes.len() != count
+    }
+
+    /// Replace the content of the element with text
+    pub fn set_text(&mut self, value: &str) {
+        self.open();
+        self.children = vec![XmlNode::Text(escape_text(value))];
+    }
+
+    /// Insert `child` after the node at `after`, or after the last node when `None`.
+    /// `own_indent` is the indentation of this element's own tag; the child is
+    /// indented like its siblings, or one level deeper than this element.
+    pub fn insert_child(
+        &mut self,
+        after: Option<usize>,
+        child: XmlElement,
+        own_indent: &str,
+        layout: &Layout,
+    ) {
+        let child_indent = self
+            .child_indent()
+            .unwrap_or_else(|| format!("{own_indent}{}", layout.indent));
+        let newline_indent = XmlNode::Text(format!("{}{child_indent}", layout.newline));
+        self.open();
+
+        if self.children.iter().all(is_whitespace) {
+            self.children = vec![
+                newline_indent,
+                XmlNode::Element(child),
+                XmlNode::Text(format!("{}{own_indent}", layout.newline)),
+            ];
+            return;
+        }
+
+        let index = match after {
+            Some(index) => index + 1,
+            None => self
+                .children
+                .iter()
+                .rposition(|node| !is_whitespace(node))
+                .map_or(0, |index| index + 1),
+        };
+        self.children.insert(index, XmlNode::Element(child));
+        self.children.insert(index, newline_indent);
+    }
+
+    /// Remove the node at `index` together with the whitespace that introduced it
+    pub fn remove_child(&mut self, index: usize) -> XmlNode {
+        let node = self.children.remove(index);
+        if index > 0 && is_whitespace(&self.children[index - 1]) {
+            self.children.remove(index - 1);
+        }
+        node
+    }
+
+    /// Whether the element has nothing but whitespace inside
+    pub fn is_empty(&self) -> bool {
+        self.children.iter().all(is_whitespace)
+    }
+
+    /// The indentation in front of the child node at `index`, if it starts a line
+    pub fn indent_before(&self, index: usize) -> Option<String> {
+        match index.checked_sub(1).map(|i| &self.children[i]) {
+            Some(XmlNode::Text(text)) if text.trim().is_empty() && text.contains('\n') => {
+                Some(text[text.rfind('\n').unwrap() + 1..].to_string())
+            }
+            _ => None,
+        }
+    }
+
+    fn child_indent(&self) -> Option<String> {
+        self.children
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| matches!(node, XmlNode::Element(_)))
+            .find_map(|(index, _)| self.indent_before(index))
+    }
+
+    /// Turn `<Name />` into `<Name></Name>` so it can hold content
+    fn open(&mut self) {
+        if self.self_closing {
+            self.self_closing = false;
+            self.tag_trailer.clear();
+            self.end_tag = format!("</{}>", self.name);
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<String> {
+        self.attributes
+            .iter()
+            .find(|attr| attr.name == name)
+            .map(XmlAttribute::value)
+    }
+
+    pub fn child_elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|node| match node {
+            XmlNode::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    /// The unescaped character data directly inside this element
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .map(|node| match node {
+                XmlNode::Text(raw) => unescape_lossy(raw),
+                XmlNode::CData(data) => data.clone(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    /// All attribute values by name, with entity references expanded
+    pub fn attribute_values(&self) -> HashMap<String, String> {
+        self.attributes
+            .iter()
+            .filter(|attr| !attr.is_namespace_declaration())
+            .map(|attr| (attr.name.clone(), attr.value()))
+            .collect()
+    }
+
+    fn write_to(&self, out: &mut String) {
+        out.push('<');
+        out.push_str(&self.name);
+        for attr in &self.attributes {
+            out.push_str(&attr.leading);
+            out.push_str(&attr.name);
+            out.push_str(&attr.separator);
+            out.push(attr.quote);
+            out.push_str(&attr.raw_value);
+            out.push(attr.quote);
+        }
+        out.push_str(&self.tag_trailer);
+        if self.self_closing {
+            out.push_str("/>");
+            return;
+        }
+        out.push('>');
+        for child in &self.children {
+            child.write_to(out);
+        }
+        out.push_str(&self.end_tag);
+    }
+}
+
+impl XmlNode {
+    fn write_to(&self, out: &mut String) {
+        match self {
+            XmlNode::Element(element) => element.write_to(out),
+            XmlNode::Text(raw) | XmlNode::Markup(raw) => out.push_str(raw),
+            XmlNode::Comment(body) => {
+                out.push_str("<!--");
+                out.push_str(body);
+                out.push_str("-->");
+            }
+            XmlNode::CData(body) => {
+                out.push_str("<![CDATA[");
+                out.push_str(body);
+                out.push_str("]]>");
+            }
+        }
+    }
+}
+
+/// A project file as a lossless XML tree, the counterpart of MSBuild's
+/// `ProjectRootElement`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectRootElement {
+    pub path: PathBuf,
+    /// Top-level nodes: the declaration, comments, whitespace and the root element
+    pub nodes: Vec<XmlNode>,
+    has_bom: bool,
+    /// How the file was encoded; saving uses the same encoding
+    pub encoding: TextEncoding,
+}
+
+impl ProjectRootElement {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::load_from(&PhysicalFileSystem, path.as_ref())
+    }
+
+    pub fn load_from(file_system: &dyn FileSystem, path: &Path) -> Result<Self> {
+        let bytes = file_system.read(path)?;
+        Self::from_bytes(&bytes, path.to_path_buf())
+    }
+
+    /// Read project XML from any reader; `path` is used for locations and saving
+    pub fn from_reader(mut reader: impl Read, path: PathBuf) -> Result<Self> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes, path)
+    }
+
+    /// Parse an encoded file, detecting its encoding from the byte order mark or
+    /// the XML declaration
+    pub fn from_bytes(bytes: &[u8], path: PathBuf) -> Result<Self> {
+        let (content, encoding) =
+            encoding::decode(bytes).map_err(|e| anyhow!("{}: error: {e}", path.display()))?;
+        let mut root = Self::parse(&content, path)?;
+        root.encoding = encoding;
+        Ok(root)
+    }
+
+    /// Parse project XML; `path` is used for locations and as the save target
+    pub fn parse(content: &str, path: PathBuf) -> Result<Self> {
+        let (has_bom, content) = match content.strip_prefix(BOM) {
+            Some(rest) => (true, rest),
+            None => (false, content),
+        };
+        let nodes = TreeBuilder::new(content, &path).build()?;
+        let root = Self {
+            path,
+            nodes,
+            has_bom,
+            encoding: TextEncoding::Utf8,
+        };
+        if root.root_element().is_none() {
+            return Err(anyhow!("{}: error: No root element", root.path.display()));
+        }
+        Ok(root)
+    }
+
+    /// The `<Project>` element
+    pub fn project(&self) -> &XmlElement {
+        self.root_element()
+            .expect("parse rejects documents without a root element")
+    }
+
+    pub fn project_mut(&mut self) -> &mut XmlElement {
+        self.nodes
+            .iter_mut()
+            .find_map(|node| match node {
+                XmlNode::Element(element) => Some(element),
+                _ => None,
+            })
+            .expect("parse rejects documents without a root element")
+    }
+
+    /// The indentation unit and line ending this file already uses
+    pub fn layout(&self) -> Layout {
+        let project = self.project();
+        let indent = project
+            .child_indent()
+            .filter(|indent| !indent.is_empty())
+            .unwrap_or_else(|| "  ".to_string());
+        let newline = if self.to_string().contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        Layout {
+            indent,
+            newline: newline.to_string(),
+        }
+    }
+
+    fn root_element(&self) -> Option<&XmlElement> {
+        self.nodes.iter().find_map(|node| match node {
+            XmlNode::Element(element) => Some(element),
+            _ => None,
+        })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_as(&self.path)
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.save_to(&PhysicalFileSystem, path.as_ref())
+    }
+
+    /// Write the document to `path` on the given file system
+    pub fn save_to(&self, file_system: &dyn FileSystem, path: &Path) -> Result<()> {
+        file_system.write(path, &self.to_bytes())
+    }
+
+    /// The document in its original encoding, byte order mark included
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encoding.encode(&self.to_string())
+    }
+
+    /// Normalize indentation to `indent` per level and quote every attribute with
+    /// double quotes. Comments, blank lines between elements and the text of
+    /// leaf elements are kept.
+    pub fn format(&mut self, indent: &str) {
+        let newline = if self.to_string().contains("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let ends_with_newline =
+            matches!(self.nodes.last(), Some(XmlNode::Text(t)) if t.contains('\n'));
+
+        let nodes = std::mem::take(&mut self.nodes);
+        let mut formatted = Vec::new();
+        for node in nodes {
+            match node {
+                XmlNode::Text(text) if text.trim().is_empty() => {}
+                XmlNode::Element(mut element) => {
+                    format_element(&mut element, indent, 0, newline);
+                    formatted.push(XmlNode::Element(element));
+                }
+                other => formatted.push(other),
+            }
+        }
+        for node in formatted {
+            if !self.nodes.is_empty() {
+                self.nodes.push(XmlNode::Text(newline.to_string()));
+            }
+            self.nodes.push(node);
+        }
+        if ends_with_newline {
+            self.nodes.push(XmlNode::Text(newline.to_string()));
+        }
+    }
+}
+
+impl fmt::Display for ProjectRootElement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        if self.has_bom {
+            out.push(BOM);
+        }
+        for node in &self.nodes {
+            node.write_to(&mut out);
+        }
+        f.write_str(&out)
+    }
+}
+
+fn format_element(element: &mut XmlElement, indent: &str, depth: usize, newline: &str) {
+    for attr in &mut element.attributes {
+        attr.leading = " ".to_string();
+        attr.separator = "=".to_string();
+        if attr.quote != '"' {
+            attr.raw_value = attr.raw_value.replace('"', "&quot;");
+            attr.quote = '"';
+        }
+    }
+    element.tag_trailer = if element.self_closing {
+        " ".to_string()
+    } else {
+        String::new()
+    };
+    if !element.self_closing {
+        element.end_tag = format!("</{}>", element.name);
+    }
+
+    // Leaf elements keep their content untouched; it is a property value or task text
+    let has_text = element.children.iter().any(|node| match node {
+        XmlNode::Text(text) => !text.trim().is_empty(),
+        XmlNode::CData(_) => true,
+        _ => false,
+    });
+    let has_markup = element
+        .children
+        .iter()
+        .any(|node| matches!(node, XmlNode::Element(_) | XmlNode::Comment(_)));
+    if has_text || !has_markup {
+        return;
+    }
+
+    let child_indent = indent.repeat(depth + 1);
+    let mut children = Vec::new();
+    let mut blank_line = false;
+    for node in std::mem::take(&mut element.children) {
+        match node {
+            XmlNode::Text(text) => {
+                // Keep at most one blank line between nodes
+                blank_line = text.matches('\n').count() > 1;
+            }
+            mut node => {
+                if let XmlNode::Element(ref mut child) = node {
+                    format_element(child, indent, depth + 1, newline);
+                }
+                let separator = if blank_line {
+                    newline.repeat(2)
+                } else {
+                    newline.to_string()
+                };
+                children.push(XmlNode::Text(separator + &child_indent));
+                children.push(node);
+                blank_line = false;
+            }
+        }
+    }
+    let separator = if blank_line {
+        newline.repeat(2)
+    } else {
+        newline.to_string()
+    };
+    children.push(XmlNode::Text(separator + &indent.repeat(depth)));
+    element.children = children;
+}
+
+fn is_whitespace(node: &XmlNode) -> bool {
+    matches!(node, XmlNode::Text(text) if text.trim().is_empty())
+}
+
+fn escape_text(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attribute(value: &str, quote: char) -> String {
+    let escaped = value.replace('&', "&amp;").replace('<', "&lt;");
+    match quote {
+        '"' => escaped.replace('"', "&quot;"),
+        _ => escaped.replace('\'', "&apos;"),
+    }
+}
+
+fn unescape_lossy(raw: &str) -> String {
+    unescape(raw)
+        .map(|value| value.into_owned())
+        .unwrap_or_else(|_| raw.to_string())
+}
+
+/// Resolve the namespace of `element` against its own declarations and those of
+/// its ancestors, returning its own declarations as `(prefix, uri)` pairs
+fn resolve_namespace(
+    element: &mut XmlElement,
+    scopes: &[Vec<(String, String)>],
+) -> Result<Vec<(String, String)>> {
+    let declarations: Vec<(String, String)> = element
+        .attributes
+        .iter()
+        .filter(|attr| attr.is_namespace_declaration())
+        .map(|attr| {
+            let prefix = attr.name.strip_prefix("xmlns").unwrap_or_default();
+            (prefix.trim_start_matches(':').to_string(), attr.value())
+        })
+        .collect();
+
+    let prefix = element
+        .name
+        .split_once(':')
+        .map_or("", |(prefix, _)| prefix);
+    let uri = declarations
+        .iter()
+        .rev()
+        .chain(scopes.iter().rev().flat_map(|scope| scope.iter().rev()))
+        .find(|(declared, _)| declared == prefix)
+        .map(|(_, uri)| uri.clone());
+    if uri.is_none() && !prefix.is_empty() && prefix != "xml" {
+        return Err(element
+            .location
+            .error(format!("Undeclared namespace prefix `{prefix}`")));
+    }
+    // xmlns="" puts an element back in no namespace
+    element.namespace = uri.filter(|uri| !uri.is_empty());
+    Ok(declarations)
+}
+
+/// Builds the node tree from raw slices of the source so nothing is normalized
+struct TreeBuilder<'a> {
+    content: &'a str,
+    file: &'a Path,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> TreeBuilder<'a> {
+    fn new(content: &'a str, file: &'a Path) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(content.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            content,
+            file,
+            line_starts,
+        }
+    }
+
+    fn location(&self, offset: usize) -> SourceLocation {
+        let offset = offset.min(self.content.len());
+        let line = self.line_starts.partition_point(|&start| start <= offset);
+        let line_start = self.line_starts[line - 1];
+        let column = self.content[line_start..offset].chars().count() + 1;
+        SourceLocation::new(self.file.to_path_buf(), line, column)
+    }
+
+    fn build(&self) -> Result<Vec<XmlNode>> {
+        let mut reader = Reader::from_str(self.content);
+        let mut stack: Vec<XmlElement> = Vec::new();
+        // Namespace declarations of each open element, parallel to `stack`
+        let mut scopes: Vec<Vec<(String, String)>> = Vec::new();
+        let mut top_level = Vec::new();
+
+        loop {
+            let start = reader.buffer_position() as usize;
+            let event = match reader.read_event() {
+                Ok(event) => event,
+                Err(e) => {
+                    let location = self.location(reader.error_position() as usize);
+                    return Err(location.error(format!("Error parsing XML: {e}")));
+                }
+            };
+            let raw = &self.content[start..reader.buffer_position() as usize];
+
+            let node = match event {
+                Event::Start(_) => {
+                    let mut element = self.parse_start_tag(raw, start, false)?;
+                    scopes.push(resolve_namespace(&mut element, &scopes)?);
+                    stack.push(element);
+                    continue;
+                }
+                Event::Empty(_) => {
+                    let mut element = self.parse_start_tag(raw, start, true)?;
+                    resolve_namespace(&mut element, &scopes)?;
+                    XmlNode::Element(element)
+                }
+                Event::End(_) => {
+                    scopes.pop();
+                    let mut element = stack
+                        .pop()
+                        .ok_or_else(|| self.location(start).error("Unexpected end tag"))?;
+                    element.end_tag = raw.to_string();
+                    XmlNode::Element(element)
+                }
+                Event::Text(_) | Event::GeneralRef(_) => XmlNode::Text(raw.to_string()),
+                Event::Comment(_) => XmlNode::Comment(raw[4..raw.len() - 3].to_string()),
+                Event::CData(_) => XmlNode::CData(raw[9..raw.len() - 3].to_string()),
+                Event::Decl(_) | Event::PI(_) | Event::DocType(_) => {
+                    XmlNode::Markup(raw.to_string())
+                }
+                Event::Eof => break,
+            };
+
+            let siblings = match stack.last_mut() {
+                Some(parent) => &mut parent.children,
+                None => &mut top_level,
+            };
+            // Entity references arrive as separate events; keep text runs together
+            match (siblings.last_mut(), node) {
+                (Some(XmlNode::Text(previous)), XmlNode::Text(text)) => previous.push_str(&text),
+                (_, node) => siblings.push(node),
+            }
+        }
+
+        if let Some(open) = stack.last() {
+            return Err(open
+                .location
+                .error(format!("Element <{}> is never closed", open.name)));
+        }
+        Ok(top_level)
+    }
+
+    /// Split a raw start tag such as `<Name a="1"  b='2' />` into its parts
+    fn parse_start_tag(&self, raw: &str, offset: usize, self_closing: bool) -> Result<XmlElement> {
+        let location = self.location(offset);
+        let body = raw
+            .strip_prefix('<')
+            .and_then(|rest| rest.strip_suffix('>'))
+            .ok_or_else(|| location.error("Malformed start tag"))?;
+        let body = if self_closing {
+            body.strip_suffix('/')
+                .ok_or_else(|| location.error("Malformed start tag"))?
+        } else {
+            body
+        };
+
+        let name_end = body.find(|c: char| c.is_whitespace()).unwrap_or(body.len());
+        let name = body[..name_end].to_string();
+        let mut rest = &body[name_end..];
+        let mut attributes = Vec::new();
+
+        loop {
+            let trimmed = rest.trim_start();
+            let leading = &rest[..rest.len() - trimmed.len()];
+            if trimmed.is_empty() {
+                let tag_trailer = leading.to_string();
+                return Ok(XmlElement {
+                    name,
+                    attributes,
+                    children: Vec::new(),
+                    self_closing,
+                    tag_trailer,
+                    end_tag: String::new(),
+                    namespace: None,
+                    location,
+                });
+            }
+
+            let malformed = || location.error(format!("Malformed attribute in <{name}>"));
+            let eq = trimmed.find('=').ok_or_else(malformed)?;
+            let attr_name = trimmed[..eq].trim_end();
+            let after_eq = &trimmed[eq + 1..];
+            let value_start = after_eq.trim_start();
+            let separator = &trimmed[attr_name.len()..trimmed.len() - value_start.len()];
+            let quote = value_start.chars().next().ok_or_else(malformed)?;
+            if quote != '"' && quote != '\'' {
+                return Err(malformed());
+            }
+            let value_end = value_start[1..].find(quote).ok_or_else(malformed)? + 1;
+
+            attributes.push(XmlAttribute {
+                leading: leading.to_string(),
+                name: attr_name.to_string(),
+                separator: separator.to_string(),
+                quote,
+                raw_value: value_start[1..value_end].to_string(),
+            });
+            rest = &value_start[value_end + 1..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_is_lossless() -> Result<()> {
+        let xml_content = concat!(
+            "\u{feff}<?xml version=\"1.0\" encoding=\"utf-8\"?>\r\n",
+            "<!-- Leading comment -->\r\n",
+            "<Project   DefaultTargets='Build' >\r\n",
+            "  <PropertyGroup Label = \"Globals\">\r\n",
+            "    <Text>a &amp; b<![CDATA[ <c> ]]></Text>\r\n",
+            "    <Empty/>\r\n",
+            "  </PropertyGroup >\r\n",
+            "\r\n",
+            "\t<UnknownThing xmlns:x=\"urn:x\"><x:Child /></UnknownThing>\r\n",
+            "</Project>",
+        );
+
+        let root = ProjectRootElement::parse(xml_content, PathBuf::from("test.proj"))?;
+        assert_eq!(root.to_string(), xml_content);
+
+        let group = root.project().child_elements().next().unwrap();
+        assert_eq!(group.attribute("Label"), Some("Globals".to_string()));
+        let unknown = root.project().child_elements().nth(1).unwrap();
+        let child = unknown.child_elements().next().unwrap();
+        assert_eq!(child.local_name(), "Child");
+        assert_eq!(child.namespace.as_deref(), Some("urn:x"));
+        assert_eq!(child.msbuild_name(), None);
+        let text = group.child_elements().next().unwrap();
+        assert_eq!(text.text(), "a & b <c> ");
+        assert_eq!((text.location.line, text.location.column), (5, 5));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_utf16_file_round_trips_byte_for_byte() -> Result<()> {
+        let text = "\u{feff}<?xml version=\"1.0\" encoding=\"utf-16\"?>\r\n<Project>\r\n  <PropertyGroup>\r\n    <Name>Caf\u{e9}</Name>\r\n  </PropertyGroup>\r\n</Project>";
+        let bytes = TextEncoding::Utf16Le.encode(text);
+
+        let root = ProjectRootElement::from_bytes(&bytes, PathBuf::from("test.proj"))?;
+        assert_eq!(root.encoding, TextEncoding::Utf16Le);
+        assert_eq!(root.to_bytes(), bytes);
+        let property = root.project().child_elements().next().unwrap();
+        assert_eq!(
+            property.child_elements().next().unwrap().text(),
+            "Caf\u{e9}"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_to_file_system() -> Result<()> {
+        let fs = crate::file_system::InMemoryFileSystem::new()
+            .with_file("/repo/app.proj", "<Project>\n</Project>");
+        let path = Path::new("/repo/app.proj");
+
+        let mut root = ProjectRootElement::load_from(&fs, path)?;
+        root.set_property("Configuration", "Release");
+        root.save_to(&fs, path)?;
+
+        assert_eq!(fs.read(path)?, root.to_bytes());
+        assert_eq!(
+            ProjectRootElement::load_from(&fs, path)?.to_string(),
+            root.to_string()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_normalizes_indentation_and_quotes() -> Result<()> {
+        let xml_content = r#"<Project>
+<!-- Settings -->
+      <PropertyGroup   Condition='$(A) == "x"'>
+ <Configuration>  Debug  </Configuration>
+</PropertyGroup>
+
+
+    <Target Name="Build"><Message Text='Hi'/></Target>
+</Project>
+"#;
+
+        let mut root = ProjectRootElement::parse(xml_content, PathBuf::from("test.proj"))?;
+        root.format("  ");
+        assert_eq!(
+            root.to_string(),
+            r#"<Project>
+  <!-- Settings -->
+  <PropertyGroup Condition="$(A) == &quot;x&quot;">
+    <Configuration>  Debug  </Configuration>
+  </PropertyGroup>
+
+  <Target Name="Build">
+    <Message Text="Hi" />
+  </Target>
+</Project>
+"#
+        );
+
+        Ok(())
+    }
+}
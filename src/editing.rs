@@ -0,0 +1,427 @@
+//! Programmatic editing of project files.
+//!
+//! Edits are made on the construction tree, so everything they do not touch keeps
+//! its formatting and new elements follow the indentation already in the file.
+//! Call [`ProjectRootElement::save`] to write the result.
+
+use anyhow::{Result, bail};
+
+use crate::construction::{Layout, ProjectRootElement, XmlElement, XmlNode};
+
+impl ProjectRootElement {
+    /// Set a property the way MSBuild's `ProjectRootElement.AddProperty` does: update
+    /// the last definition without a condition on itself or its group, otherwise add
+    /// it to the first unconditioned PropertyGroup, creating one when needed.
+    pub fn set_property(&mut self, name: &str, value: &str) {
+        let layout = self.layout();
+        let project = self.project_mut();
+
+        let mut existing = None;
+        for (group_index, group) in unconditioned_children(project, "PropertyGroup") {
+            if let Some((index, _)) = unconditioned_children(group, name).last() {
+                existing = Some((group_index, index));
+            }
+        }
+        if let Some((group_index, index)) = existing {
+            element_mut(element_mut(project, group_index), index).set_text(value);
+            return;
+        }
+
+        let first_group = unconditioned_children(project, "PropertyGroup")
+            .next()
+            .map(|(index, _)| index);
+        let group_index = match first_group {
+            Some(index) => index,
+            None => add_group(project, "PropertyGroup", &["PropertyGroup"], &layout),
+        };
+        let mut property = XmlElement::new(name);
+        property.set_text(value);
+        insert_into(project, group_index, property, &layout);
+    }
+
+    /// Remove every definition of a property, dropping groups left empty.
+    /// Returns how many definitions were removed.
+    pub fn remove_property(&mut self, name: &str) -> usize {
+        remove_grouped(self.project_mut(), "PropertyGroup", |element| {
+            element.msbuild_name() == Some(name)
+        })
+    }
+
+    /// Add an item with metadata written as attributes. It goes into the first
+    /// unconditioned ItemGroup that already holds items of the same type, or into
+    /// a new ItemGroup.
+    pub fn add_item(&mut self, item_type: &str, include: &str, metadata: &[(String, String)]) {
+        let layout = self.layout();
+        let project = self.project_mut();
+
+        let mut item = XmlElement::new(item_type).with_attribute("Include", include);
+        for (name, value) in metadata {
+            item.set_attribute(name, value);
+        }
+
+        let same_type_group = unconditioned_children(project, "ItemGroup")
+            .find(|(_, group)| {
+                group
+                    .child_elements()
+                    .any(|e| e.msbuild_name() == Some(item_type))
+            })
+            .map(|(index, _)| index);
+        let group_index = match same_type_group {
+            Some(index) => index,
+            None => add_group(
+                project,
+                "ItemGroup",
+                &["ItemGroup", "PropertyGroup"],
+                &layout,
+            ),
+        };
+        insert_into(project, group_index, item, &layout);
+    }
+
+    /// Set metadata on every item of `item_type` whose Include is `include`, updating
+    /// metadata elements in place and writing new metadata as attributes.
+    pub fn set_item_metadata(
+        &mut self,
+        item_type: &str,
+        include: &str,
+        name: &str,
+        value: &str,
+    ) -> Result<()> {
+        let mut found = false;
+        for group in child_elements_mut(self.project_mut(), "ItemGroup") {
+            for item in child_elements_mut(group, item_type) {
+                if item.attribute("Include").as_deref() != Some(include) {
+                    continue;
+                }
+                found = true;
+                let metadata_element = find_children(item, name).first().copied();
+                match metadata_element {
+                    Some(index) => element_mut(item, index).set_text(value),
+                    None => item.set_attribute(name, value),
+                }
+            }
+        }
+        if !found {
+            bail!("No {item_type} item with Include=\"{include}\" found");
+        }
+        Ok(())
+    }
+
+    /// Remove items of `item_type` whose Include is `include`, dropping groups left
+    /// empty. Returns how many items were removed.
+    pub fn remove_item(&mut self, item_type: &str, include: &str) -> usize {
+        remove_grouped(self.project_mut(), "ItemGroup", |element| {
+            element.msbuild_name() == Some(item_type)
+                && element.attribute("Include").as_deref() == Some(include)
+        })
+    }
+
+    /// Append a new, empty target at the end of the project
+    pub fn add_target(&mut self, name: &str, depends_on: Option<&str>) -> Result<()> {
+        let layout = self.layout();
+        let project = self.project_mut();
+        if project.msbuild_children().any(|(element_name, target)| {
+            element_name == "Target" && target.attribute("Name").as_deref() == Some(name)
+        }) {
+            bail!("Target {name} already exists");
+        }
+
+        let mut target = XmlElement::new("Target").with_attribute("Name", name);
+        if let Some(depends_on) = depends_on {
+            target.set_attribute("DependsOnTargets", depends_on);
+        }
+        project.insert_child(None, target, "", &layout);
+        Ok(())
+    }
+
+    /// Append a task to the end of an existing target
+    #[allow(dead_code)] // Public API method for library users
+    pub fn add_task(&mut self, target_name: &str, task: XmlElement) -> Result<()> {
+        let layout = self.layout();
+        let project = self.project_mut();
+        let Some(index) = project.children.iter().position(|node| {
+            matches!(node, XmlNode::Element(target)
+                if target.msbuild_name() == Some("Target") && target.attribute("Name").as_deref() == Some(target_name))
+        }) else {
+            bail!("Target {target_name} not found");
+        };
+        insert_into(project, index, task, &layout);
+        Ok(())
+    }
+
+    pub fn remove_target(&mut self, name: &str) -> usize {
+        remove_children(self.project_mut(), |element| {
+            element.msbuild_name() == Some("Target")
+                && element.attribute("Name").as_deref() == Some(name)
+        })
+    }
+
+    /// Add an import after the last existing import, or at the end of the project
+    pub fn add_import(&mut self, project_path: &str, condition: Option<&str>) {
+        let layout = self.layout();
+        let project = self.project_mut();
+
+        let mut import = XmlElement::new("Import").with_attribute("Project", project_path);
+        if let Some(condition) = condition {
+            import.set_attribute("Condition", condition);
+        }
+        let after = find_children(project, "Import").last().copied();
+        project.insert_child(after, import, "", &layout);
+    }
+
+    pub fn remove_import(&mut self, project_path: &str) -> usize {
+        remove_children(self.project_mut(), |element| {
+            element.msbuild_name() == Some("Import")
+                && element.attribute("Project").as_deref() == Some(project_path)
+        })
+    }
+}
+
+fn element_mut(parent: &mut XmlElement, index: usize) -> &mut XmlElement {
+    match &mut parent.children[index] {
+        XmlNode::Element(element) => element,
+        _ => unreachable!("index {index} does not refer to an element"),
+    }
+}
+
+fn child_elements_mut<'a>(
+    parent: &'a mut XmlElement,
+    name: &'a str,
+) -> impl Iterator<Item = &'a mut XmlElement> {
+    parent
+        .children
+        .iter_mut()
+        .filter_map(move |node| match node {
+            XmlNode::Element(element) if element.msbuild_name() == Some(name) => Some(element),
+            _ => None,
+        })
+}
+
+/// Node indices of the child elements called `name`
+fn find_children(parent: &XmlElement, name: &str) -> Vec<usize> {
+    parent
+        .children
+        .iter()
+        .enumerate()
+        .filter(|(_, node)| matches!(node, XmlNode::Element(element) if element.msbuild_name() == Some(name)))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Child elements called `name` without a Condition, with their node indices
+fn unconditioned_children<'a>(
+    parent: &'a XmlElement,
+    name: &'a str,
+) -> impl Iterator<Item = (usize, &'a XmlElement)> {
+    parent
+        .children
+        .iter()
+        .enumerate()
+        .filter_map(move |(index, node)| match node {
+            XmlNode::Element(element)
+                if element.msbuild_name() == Some(name)
+                    && element.attribute("Condition").is_none() =>
+            {
+                Some((index, element))
+            }
+            _ => None,
+        })
+}
+
+/// Insert an empty group after the last element named in `after`, or before the
+/// first element of the project. Returns the node index of the new group.
+fn add_group(project: &mut XmlElement, name: &str, after: &[&str], layout: &Layout) -> usize {
+    let group = XmlElement::new(name);
+    let last_anchor = after
+        .iter()
+        .filter_map(|anchor| find_children(project, anchor).last().copied())
+        .max();
+    if let Some(anchor) = last_anchor {
+        project.insert_child(Some(anchor), group, "", layout);
+        return anchor + 2;
+    }
+
+    match project
+        .children
+        .iter()
+        .position(|node| matches!(node, XmlNode::Element(_)))
+    {
+        Some(first) => {
+            // Take the first slot and give the displaced element the same indentation
+            let indent = project.indent_before(first).unwrap_or_default();
+            project
+                .children
+                .insert(first, XmlNode::Text(format!("{}{indent}", layout.newline)));
+            project.children.insert(first, XmlNode::Element(group));
+            first
+        }
+        None => {
+            project.insert_child(None, group, "", layout);
+            project
+                .children
+                .iter()
+                .rposition(|node| matches!(node, XmlNode::Element(_)))
+                .expect("group was just inserted")
+        }
+    }
+}
+
+/// Append `child` to the project-level element at `index`
+fn insert_into(project: &mut XmlElement, index: usize, child: XmlElement, layout: &Layout) {
+    let indent = project.indent_before(index).unwrap_or_default();
+    element_mut(project, index).insert_child(None, child, &indent, layout);
+}
+
+fn remove_children(parent: &mut XmlElement, matches: impl Fn(&XmlElement) -> bool) -> usize {
+    let mut removed = 0;
+    let mut index = parent.children.len();
+    while index > 0 {
+        index -= 1;
+        if matches!(&parent.children[index], XmlNode::Element(element) if matches(element)) {
+            parent.remove_child(index);
+            removed += 1;
+            index = index.min(parent.children.len());
+        }
+    }
+    removed
+}
+
+/// Remove matching elements from project-level groups called `group_name` and drop
+/// groups that end up empty
+fn remove_grouped(
+    project: &mut XmlElement,
+    group_name: &str,
+    matches: impl Fn(&XmlElement) -> bool,
+) -> usize {
+    let mut removed = 0;
+    let mut emptied = Vec::new();
+    for index in find_children(project, group_name) {
+        let group = element_mut(project, index);
+        let count = remove_children(group, &matches);
+        if count > 0 && group.is_empty() {
+            emptied.push(index);
+        }
+        removed += count;
+    }
+    for index in emptied.into_iter().rev() {
+        project.remove_child(index);
+    }
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const PROJECT: &str = r#"<Project>
+    <!-- Settings -->
+    <PropertyGroup>
+        <Configuration>Debug</Configuration>
+    </PropertyGroup>
+
+    <ItemGroup>
+        <PackageReference Include="Serilog" Version="2.0.0" />
+    </ItemGroup>
+</Project>
+"#;
+
+    #[test]
+    fn test_edits_follow_existing_formatting() -> Result<()> {
+        let mut root = ProjectRootElement::parse(PROJECT, PathBuf::from("test.proj"))?;
+        root.set_property("Configuration", "Release");
+        root.set_property("Nullable", "enable");
+        root.add_item(
+            "PackageReference",
+            "xunit",
+            &[("Version".to_string(), "2.9.0".to_string())],
+        );
+        root.set_item_metadata("PackageReference", "Serilog", "Version", "3.1.1")?;
+        root.add_target("Pack", Some("Build"))?;
+        root.add_task(
+            "Pack",
+            XmlElement::new("Message").with_attribute("Text", "Packing"),
+        )?;
+        root.add_import("common.targets", None);
+
+        assert_eq!(
+            root.to_string(),
+            r#"<Project>
+    <!-- Settings -->
+    <PropertyGroup>
+        <Configuration>Release</Configuration>
+        <Nullable>enable</Nullable>
+    </PropertyGroup>
+
+    <ItemGroup>
+        <PackageReference Include="Serilog" Version="3.1.1" />
+        <PackageReference Include="xunit" Version="2.9.0" />
+    </ItemGroup>
+    <Target Name="Pack" DependsOnTargets="Build">
+        <Message Text="Packing" />
+    </Target>
+    <Import Project="common.targets" />
+</Project>
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_removals_drop_empty_groups() -> Result<()> {
+        let mut root = ProjectRootElement::parse(PROJECT, PathBuf::from("test.proj"))?;
+        assert_eq!(root.remove_item("PackageReference", "Serilog"), 1);
+        assert_eq!(root.remove_property("Configuration"), 1);
+        assert_eq!(root.remove_property("Missing"), 0);
+
+        assert_eq!(
+            root.to_string(),
+            "<Project>\n    <!-- Settings -->\n</Project>\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_new_groups_are_created_where_msbuild_puts_them() -> Result<()> {
+        let mut root = ProjectRootElement::parse(
+            "<Project>\n  <Target Name=\"Build\" />\n</Project>",
+            PathBuf::from("test.proj"),
+        )?;
+        root.add_item("Compile", "a.cs", &[]);
+        root.set_property("Configuration", "Debug");
+
+        assert_eq!(
+            root.to_string(),
+            r#"<Project>
+  <PropertyGroup>
+    <Configuration>Debug</Configuration>
+  </PropertyGroup>
+  <ItemGroup>
+    <Compile Include="a.cs" />
+  </ItemGroup>
+  <Target Name="Build" />
+</Project>"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_targets_are_found_in_prefixed_documents() -> Result<()> {
+        let mut root = ProjectRootElement::parse(
+            r#"<msb:Project xmlns:msb="http://schemas.microsoft.com/developer/msbuild/2003">
+  <msb:Target Name="Build" />
+</msb:Project>"#,
+            PathBuf::from("test.proj"),
+        )?;
+
+        assert!(root.add_target("Build", None).is_err());
+        root.add_task(
+            "Build",
+            XmlElement::new("Message").with_attribute("Text", "Building"),
+        )?;
+        let (_, target) = root.project().msbuild_children().next().unwrap();
+        assert_eq!(target.msbuild_children().next().unwrap().0, "Message");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+//! Character encodings of project files.
+//!
+//! Visual Studio and older Windows tooling save projects as UTF-16 or with a byte
+//! order mark, and some declare a legacy code page in the XML declaration. The
+//! encoding is detected the way XML parsers do: from the byte order mark, then
+//! from the first bytes, then from the `encoding` in the declaration.
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+
+/// The byte order mark as it appears at the start of decoded text
+pub const BOM: char = '\u{feff}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+/// Characters for bytes 0x80..=0x9F in Windows-1252; the rest match Latin-1.
+/// Unassigned bytes map to the C1 control with the same value, as browsers do.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl TextEncoding {
+    fn from_label(label: &str) -> Option<Self> {
+        match label.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "us-ascii" | "ascii" => Some(Self::Utf8),
+            "utf-16" | "utf-16le" | "unicode" => Some(Self::Utf16Le),
+            "utf-16be" => Some(Self::Utf16Be),
+            "iso-8859-1" | "latin1" | "l1" => Some(Self::Latin1),
+            "windows-1252" | "cp1252" => Some(Self::Windows1252),
+            _ => None,
+        }
+    }
+
+    /// Decode bytes that carry no byte order mark
+    fn decode_raw(self, bytes: &[u8]) -> Result<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes.to_vec())
+                .map_err(|e| anyhow!("Invalid UTF-8 at byte {}", e.utf8_error().valid_up_to())),
+            Self::Utf16Le | Self::Utf16Be => {
+                if !bytes.len().is_multiple_of(2) {
+                    return Err(anyhow!("Truncated UTF-16 content"));
+                }
+                let units: Vec<u16> = bytes
+                    .chunks_exact(2)
+                    .map(|pair| match self {
+                        Self::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+                        _ => u16::from_be_bytes([pair[0], pair[1]]),
+                    })
+                    .collect();
+                String::from_utf16(&units).map_err(|_| anyhow!("Invalid UTF-16 content"))
+            }
+            Self::Latin1 => Ok(bytes.iter().map(|&b| b as char).collect()),
+            Self::Windows1252 => Ok(bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    _ => b as char,
+                })
+                .collect()),
+        }
+    }
+
+    /// Encode text; a leading [`BOM`] becomes this encoding's byte order mark.
+    /// Characters the encoding cannot hold are written as XML character references.
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        match self {
+            Self::Utf8 => text.as_bytes().to_vec(),
+            Self::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            Self::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Self::Latin1 | Self::Windows1252 => {
+                let mut bytes = Vec::with_capacity(text.len());
+                for c in text.chars() {
+                    match self.single_byte(c) {
+                        Some(byte) => bytes.push(byte),
+                        None => bytes.extend(format!("&#x{:X};", c as u32).bytes()),
+                    }
+                }
+                bytes
+            }
+        }
+    }
+
+    fn single_byte(self, c: char) -> Option<u8> {
+        let code = c as u32;
+        if self == Self::Windows1252 {
+            if let Some(index) = WINDOWS_1252_HIGH.iter().position(|&high| high == c) {
+                return Some(0x80 + index as u8);
+            }
+            if (0x80..=0x9F).contains(&code) {
+                return None;
+            }
+        }
+        u8::try_from(code).ok()
+    }
+}
+
+/// Decode a project file, returning the text (with a leading [`BOM`] when the file
+/// had one) and the encoding to write it back with
+pub fn decode(bytes: &[u8]) -> Result<(String, TextEncoding)> {
+    let (encoding, bom_len) = match bytes {
+        [0xEF, 0xBB, 0xBF, ..] => (TextEncoding::Utf8, 3),
+        [0xFF, 0xFE, ..] => (TextEncoding::Utf16Le, 2),
+        [0xFE, 0xFF, ..] => (TextEncoding::Utf16Be, 2),
+        // '<' as UTF-16 without a byte order mark
+        [b'<', 0x00, ..] => (TextEncoding::Utf16Le, 0),
+        [0x00, b'<', ..] => (TextEncoding::Utf16Be, 0),
+        _ => (declared_encoding(bytes)?, 0),
+    };
+
+    let mut text = encoding.decode_raw(&bytes[bom_len..])?;
+    if bom_len > 0 {
+        text.insert(0, BOM);
+    }
+    Ok((text, encoding))
+}
+
+/// Read `encoding="..."` from an XML declaration in ASCII-compatible bytes
+fn declared_encoding(bytes: &[u8]) -> Result<TextEncoding> {
+    if !bytes.starts_with(b"<?xml") {
+        return Ok(TextEncoding::Utf8);
+    }
+    let end = bytes
+        .windows(2)
+        .position(|w| w == b"?>")
+        .unwrap_or(bytes.len());
+    let declaration = String::from_utf8_lossy(&bytes[..end]);
+    let encoding_regex = Regex::new(r#"encoding\s*=\s*["']([^"']+)["']"#).unwrap();
+    match encoding_regex.captures(&declaration) {
+        Some(captures) => match TextEncoding::from_label(&captures[1]) {
+            // Bytes that start with ASCII '<' are not UTF-16, whatever the
+            // declaration says; tools often copy the declaration of an in-memory string
+            Some(TextEncoding::Utf16Le | TextEncoding::Utf16Be) => Ok(TextEncoding::Utf8),
+            Some(encoding) => Ok(encoding),
+            None => Err(anyhow!("Unsupported encoding: {}", &captures[1])),
+        },
+        None => Ok(TextEncoding::Utf8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_and_encode_round_trip() -> Result<()> {
+        let text = "\u{feff}<?xml version=\"1.0\" encoding=\"utf-16\"?><Project />";
+        let bytes = TextEncoding::Utf16Le.encode(text);
+        assert_eq!(&bytes[..4], &[0xFF, 0xFE, b'<', 0x00]);
+        assert_eq!(decode(&bytes)?, (text.to_string(), TextEncoding::Utf16Le));
+
+        let bytes = b"<?xml version=\"1.0\" encoding=\"Windows-1252\"?><A>\x80 caf\xe9</A>";
+        let (text, encoding) = decode(bytes)?;
+        assert_eq!(encoding, TextEncoding::Windows1252);
+        assert!(text.ends_with("<A>\u{20AC} caf\u{e9}</A>"));
+        assert_eq!(encoding.encode(&text), bytes);
+        assert_eq!(encoding.encode("\u{3042}"), b"&#x3042;");
+
+        assert!(decode(b"<?xml version=\"1.0\" encoding=\"koi8-r\"?><A />").is_err());
+        Ok(())
+    }
+}
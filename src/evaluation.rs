@@ -1,80 +1,207 @@
 use anyhow::{Result, anyhow};
-use log::{debug, info, warn};
-use std::collections::HashSet;
-use std::path::Path;
+use indexmap::IndexMap;
+use log::{debug, error, info};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::build_result::{BuildResult, TargetOutcome, TargetResult};
+use crate::construction::ProjectRootElement;
 use crate::expression::ExpressionEvaluator;
-use crate::object_model::ProjectModel;
+use crate::file_system::{FileSystem, PhysicalFileSystem};
+use crate::incremental::{self, Analysis};
+use crate::object_model::{Item, OnError, ProjectModel, Target, TargetChild, Task};
 use crate::parser::ProjectParser;
-use crate::tasks::TaskRegistry;
+use crate::properties;
+use crate::sdk::SdkResolver;
+use crate::tasks::{TaskOutputs, TaskRegistry};
+use crate::validation::{self, ValidationMode};
+
+/// Targets run so far in one `execute_target` call and their results
+#[derive(Default)]
+struct BuildState {
+    executed: HashSet<String>,
+    /// Targets whose dependencies and hooks are running, outermost first
+    in_progress: Vec<String>,
+    result: BuildResult,
+}
 
 pub struct ProjectEvaluator {
     model: ProjectModel,
     task_registry: TaskRegistry,
+    file_system: Arc<dyn FileSystem>,
+    validation_mode: ValidationMode,
+    sdk_resolver: Option<Arc<dyn SdkResolver>>,
+    environment: IndexMap<String, String>,
+    global_properties: IndexMap<String, String>,
 }
 
 impl ProjectEvaluator {
     pub fn new() -> Self {
+        Self::with_file_system(PhysicalFileSystem::shared())
+    }
+
+    /// Evaluate and build against the given file system instead of the real disk
+    pub fn with_file_system(file_system: Arc<dyn FileSystem>) -> Self {
         Self {
             model: ProjectModel::new(),
             task_registry: TaskRegistry::new(),
+            file_system,
+            validation_mode: ValidationMode::default(),
+            sdk_resolver: None,
+            environment: properties::process_environment(),
+            global_properties: IndexMap::new(),
         }
     }
 
+    /// Replace the environment variables visible as properties, which default to
+    /// those of this process. They also give the `MSBuildSDKsPath` searched for
+    /// SDKs, so an empty environment makes the build hermetic.
+    pub fn set_environment(&mut self, variables: impl IntoIterator<Item = (String, String)>) {
+        self.environment = properties::environment_properties(variables);
+    }
+
+    /// Set properties for the whole build, as `-p:Name=Value` does. Project files
+    /// see them from their first line and cannot change them unless they list
+    /// them in `TreatAsLocalProperty`.
+    pub fn set_global_properties(
+        &mut self,
+        properties: impl IntoIterator<Item = (String, String)>,
+    ) {
+        self.global_properties = properties.into_iter().collect();
+    }
+
+    /// Find SDKs with the given resolver instead of searching the directories in
+    /// the `MSBuildSDKsPath` environment variable
+    #[allow(dead_code)] // Public API method for library users
+    pub fn set_sdk_resolver(&mut self, resolver: Arc<dyn SdkResolver>) {
+        self.sdk_resolver = Some(resolver);
+    }
+
+    /// Choose whether schema violations in loaded files are warnings or errors
+    pub fn set_validation_mode(&mut self, mode: ValidationMode) {
+        self.validation_mode = mode;
+    }
+
     pub fn load_project<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
         info!("Loading project: {}", path.as_ref().display());
+        let root = ProjectRootElement::load_from(self.file_system.as_ref(), path.as_ref())?;
+        self.load_root(&root)
+    }
 
-        let mut parser = ProjectParser::new();
-        self.model = parser.parse_file(&path)?;
+    /// Load a project whose XML is held in memory, such as an unsaved editor
+    /// buffer. `path` gives the project its location for relative paths.
+    #[allow(dead_code)] // Public API method for library users
+    pub fn load_project_from_str(&mut self, content: &str, path: impl Into<PathBuf>) -> Result<()> {
+        let root = ProjectRootElement::parse(content, path.into())?;
+        info!("Loading project: {}", root.path.display());
+        self.load_root(&root)
+    }
 
-        // Set the project file path for task execution context
-        self.model
-            .set_project_file_path(path.as_ref().to_path_buf());
+    #[allow(dead_code)] // Public API method for library users
+    pub fn load_project_from_reader(
+        &mut self,
+        reader: impl Read,
+        path: impl Into<PathBuf>,
+    ) -> Result<()> {
+        let root = ProjectRootElement::from_reader(reader, path.into())?;
+        info!("Loading project: {}", root.path.display());
+        self.load_root(&root)
+    }
+
+    fn load_root(&mut self, root: &ProjectRootElement) -> Result<()> {
+        validation::enforce(root, self.validation_mode)?;
+        let mut parser = ProjectParser::with_file_system(self.file_system.clone())
+            .with_validation_mode(self.validation_mode)
+            .with_environment(self.environment.clone())
+            .with_global_properties(self.global_properties.clone());
+        if let Some(resolver) = &self.sdk_resolver {
+            parser = parser.with_sdk_resolver(resolver.clone());
+        }
+        self.model = parser.parse_root(root)?;
 
         debug!("Loaded {} properties", self.model.properties.len());
         debug!("Loaded {} item types", self.model.items.len());
         debug!("Loaded {} targets", self.model.targets.len());
+        Ok(())
+    }
 
-        // Process imports (simplified - would need to handle relative paths properly in real implementation)
-        for import in &self.model.imports.clone() {
-            let evaluator = ExpressionEvaluator::new(&self.model);
-            if let Some(condition) = &import.condition {
-                if !evaluator.evaluate_condition(condition)? {
-                    continue;
-                }
-            }
-
-            let import_path = evaluator.evaluate(&import.project)?;
-            info!("Processing import: {import_path}");
+    /// Build a target and what it depends on. A failed build is reported in the
+    /// result; use [`BuildResult::into_result`] to treat it as an error.
+    pub fn execute_target(&mut self, target_name: &str) -> BuildResult {
+        self.execute_targets(&[target_name.to_string()])
+    }
 
-            // In a real implementation, this would resolve relative paths and handle SDK imports
-            if Path::new(&import_path).exists() {
-                let mut import_parser = ProjectParser::new();
-                let import_model = import_parser.parse_file(&import_path)?;
+    /// Build targets in order after the project's `InitialTargets`, sharing one
+    /// result so each target runs at most once
+    pub fn execute_targets(&mut self, target_names: &[String]) -> BuildResult {
+        let mut state = BuildState::default();
+        if let Err(error) = self.execute_targets_in(target_names, &mut state) {
+            state.result.set_error(error);
+        }
+        if let Some(project) = self.model.get_property("MSBuildProjectFullPath").cloned() {
+            self.set_this_file_properties(Path::new(&project));
+        }
+        state.result
+    }
 
-                // Merge the imported model into the current model
-                self.merge_model(import_model)?;
-            } else {
-                warn!("Import file not found: {import_path}");
-            }
+    /// Point the `MSBuildThisFile*` properties at `file`
+    fn set_this_file_properties(&mut self, file: &Path) {
+        for (name, value) in properties::this_file_properties(file) {
+            self.model.set_reserved_property(name, value);
         }
+    }
 
+    fn execute_targets_in(
+        &mut self,
+        target_names: &[String],
+        state: &mut BuildState,
+    ) -> Result<()> {
+        for target_name in self.target_list(&self.model.initial_targets)? {
+            info!("Executing initial target: {target_name}");
+            self.execute_target_recursive(&target_name, state)?;
+        }
+        for target_name in target_names {
+            self.execute_target_recursive(target_name, state)?;
+        }
         Ok(())
     }
 
-    pub fn execute_target(&mut self, target_name: &str) -> Result<()> {
-        info!("Executing target: {target_name}");
+    /// The targets to build when none are requested: `DefaultTargets`, or else
+    /// the first target in the project
+    pub fn default_targets(&self) -> Result<Vec<String>> {
+        let targets = self.target_list(&self.model.default_targets)?;
+        if !targets.is_empty() {
+            return Ok(targets);
+        }
+        Ok(self.model.targets.keys().take(1).cloned().collect())
+    }
 
-        let mut executed_targets = HashSet::new();
-        self.execute_target_recursive(target_name, &mut executed_targets)
+    /// Expand and split target lists such as `InitialTargets`
+    fn target_list<'a>(&self, lists: impl IntoIterator<Item = &'a String>) -> Result<Vec<String>> {
+        let evaluator = ExpressionEvaluator::new(&self.model);
+        let mut targets = Vec::new();
+        for list in lists {
+            let expanded = evaluator.evaluate(list)?;
+            targets.extend(
+                expanded
+                    .split(';')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        Ok(targets)
     }
 
     fn execute_target_recursive(
-        &self,
+        &mut self,
         target_name: &str,
-        executed_targets: &mut HashSet<String>,
+        state: &mut BuildState,
     ) -> Result<()> {
-        if executed_targets.contains(target_name) {
+        if state.executed.contains(target_name) {
             debug!("Target {target_name} already executed, skipping");
             return Ok(());
         }
@@ -85,60 +212,309 @@ impl ProjectEvaluator {
             .ok_or_else(|| anyhow!("Target not found: {target_name}"))?
             .clone();
 
-        // Check target condition
-        if let Some(condition) = &target.condition {
-            let evaluator = ExpressionEvaluator::new(&self.model);
-            if !evaluator.evaluate_condition(condition)? {
-                info!("Skipping target {target_name} due to condition: {condition}");
-                return Ok(());
+        if let Some(start) = state
+            .in_progress
+            .iter()
+            .position(|name| name == target_name)
+        {
+            let mut cycle = state.in_progress[start..].to_vec();
+            cycle.push(target.name.clone());
+            let requester = state
+                .in_progress
+                .last()
+                .and_then(|name| self.model.get_target(name));
+            let location = requester.map_or(&target.location, |requester| &requester.location);
+            return Err(location.error(format!(
+                "There is a circular dependency in the target dependency graph: {}",
+                cycle.join(" -> ")
+            )));
+        }
+
+        state.in_progress.push(target.name.clone());
+        let result = self.execute_target_and_dependencies(&target, state);
+        state.in_progress.pop();
+        let result = match result {
+            Ok(result) => result,
+            Err(error) => {
+                let outcome = TargetOutcome::Failure(error.to_string());
+                state.result.add_target_result(
+                    target.name.clone(),
+                    TargetResult {
+                        outcome,
+                        items: Vec::new(),
+                    },
+                );
+                return Err(error);
             }
+        };
+        state.result.add_target_result(target.name.clone(), result);
+
+        let after: Vec<String> = self
+            .hooked_targets(&target.name, |hook| &hook.after_targets)
+            .collect();
+        for hook in after {
+            info!("Target {hook} runs after {} (AfterTargets)", target.name);
+            self.execute_target_recursive(&hook, state)?;
         }
 
-        // Execute dependencies first
-        for dependency in &target.depends_on {
-            self.execute_target_recursive(dependency, executed_targets)?;
+        Ok(())
+    }
+
+    /// Run the dependencies and `BeforeTargets` hooks of a target, then the
+    /// target itself
+    fn execute_target_and_dependencies(
+        &mut self,
+        target: &Target,
+        state: &mut BuildState,
+    ) -> Result<TargetResult> {
+        // Like during evaluation, a target defined in an import sees that file
+        // as `$(MSBuildThisFile)`
+        self.set_this_file_properties(&target.location.file);
+
+        // A target whose condition is false is skipped along with its
+        // dependencies, but targets hooked before or after it still run
+        let condition_met = match &target.condition {
+            Some(condition) => {
+                ExpressionEvaluator::new(&self.model).evaluate_condition(condition)?
+            }
+            None => true,
+        };
+
+        if condition_met {
+            // Execute dependencies first
+            for dependency in &target.depends_on {
+                if self.model.get_target(dependency).is_none() {
+                    return Err(target.location.error(format!(
+                        "Target {} depends on missing target: {dependency}",
+                        target.name
+                    )));
+                }
+                self.execute_target_recursive(dependency, state)?;
+            }
         }
 
-        info!("Executing target: {}", target.name);
-        executed_targets.insert(target_name.to_string());
+        let before: Vec<String> = self
+            .hooked_targets(&target.name, |hook| &hook.before_targets)
+            .collect();
+        for hook in before {
+            info!("Target {hook} runs before {} (BeforeTargets)", target.name);
+            self.execute_target_recursive(&hook, state)?;
+        }
 
-        // Execute tasks in the target
-        for task in &target.tasks {
-            debug!("Executing task: {}", task.name);
-            self.task_registry.execute_task(task, &self.model)?;
+        // Dependencies and hooks may be defined in other files
+        self.set_this_file_properties(&target.location.file);
+        state.executed.insert(target.name.clone());
+        if !condition_met {
+            let condition = target.condition.as_deref().unwrap_or_default();
+            info!(
+                "Skipping target {} due to condition: {condition}",
+                target.name
+            );
+            return Ok(TargetResult {
+                outcome: TargetOutcome::Skipped(format!("Condition '{condition}' was false.")),
+                items: Vec::new(),
+            });
         }
 
-        Ok(())
+        let outcome = self.execute_target_body(target, state)?;
+        Ok(TargetResult {
+            outcome,
+            items: self.target_returns(target)?,
+        })
     }
 
-    fn merge_model(&mut self, other: ProjectModel) -> Result<()> {
-        // Merge properties
-        for (name, value) in other.properties {
-            self.model.set_property(name, value);
+    /// The items named by `Returns`, or by `Outputs` when there is no `Returns`
+    fn target_returns(&self, target: &Target) -> Result<Vec<Item>> {
+        let Some(returns) = target.returns.as_ref().or(target.outputs.as_ref()) else {
+            return Ok(Vec::new());
+        };
+        let evaluator = ExpressionEvaluator::new(&self.model);
+        let item_type_regex = Regex::new(r"^@\(\s*([A-Za-z_][\w.-]*?)\s*(?:->|\))").unwrap();
+        let mut items = Vec::new();
+        for part in returns.split(';').map(str::trim) {
+            let item_type = item_type_regex
+                .captures(part)
+                .map_or(String::new(), |captures| captures[1].to_string());
+            for (name, metadata) in evaluator.evaluate_items(part)? {
+                items.push(Item {
+                    item_type: item_type.clone(),
+                    name,
+                    metadata,
+                    location: target.location.clone(),
+                });
+            }
         }
+        Ok(items)
+    }
 
-        // Merge items
-        for (_item_type, items) in other.items {
-            for item in items {
-                self.model.add_item(item);
+    /// Run a target's children, unless its outputs are up to date with its
+    /// inputs; a partial build narrows the inputs to the out-of-date items
+    fn execute_target_body(
+        &mut self,
+        target: &Target,
+        state: &mut BuildState,
+    ) -> Result<TargetOutcome> {
+        let mut narrowed = None;
+        match incremental::analyze(target, &self.model, self.file_system.as_ref())? {
+            Some(Analysis::Skip(reason)) => {
+                info!("{reason}");
+                return Ok(TargetOutcome::Skipped(reason));
+            }
+            Some(Analysis::Full(reasons)) => {
+                info!("Building target {} completely.", target.name);
+                for reason in reasons {
+                    info!("{reason}");
+                }
+            }
+            Some(Analysis::Partial {
+                item_type,
+                items,
+                reasons,
+            }) => {
+                info!(
+                    "Building target {} partially, because some output files are out of date with respect to their input files.",
+                    target.name
+                );
+                for reason in reasons {
+                    info!("{reason}");
+                }
+                let all = self.model.items.insert(item_type.clone(), items.clone());
+                narrowed = Some((item_type, all.unwrap_or_default(), items));
             }
+            None => info!("Executing target: {}", target.name),
         }
 
-        // Merge targets
-        for (_name, target) in other.targets {
-            self.model.add_target(target);
+        let result = self.execute_target_children(target);
+
+        // Restore the full item list with the target's changes to it
+        if let Some((item_type, all, narrowed)) = narrowed {
+            let items = self.model.items.entry(item_type).or_default();
+            *items = merge_partial_items(all, &narrowed, std::mem::take(items));
         }
 
-        // Merge imports
-        for import in other.imports {
-            self.model.add_import(import);
+        if let Err(error) = result {
+            self.run_on_error_targets(target, state);
+            return Err(error);
         }
+        Ok(TargetOutcome::Success)
+    }
+
+    /// Targets that name `anchor` in the given hook list, in declaration order
+    fn hooked_targets<'a>(
+        &'a self,
+        anchor: &'a str,
+        hooks: impl Fn(&Target) -> &Vec<String> + 'a,
+    ) -> impl Iterator<Item = String> + 'a {
+        self.model
+            .targets
+            .values()
+            .filter(move |target| hooks(target).iter().any(|name| name == anchor))
+            .map(|target| target.name.clone())
+    }
 
-        // Merge using tasks
-        for (task_name, assembly) in other.using_tasks {
-            self.model.add_using_task(task_name, assembly);
+    /// Execute tasks and groups in document order; groups change the model that
+    /// later tasks and targets see
+    fn execute_target_children(&mut self, target: &Target) -> Result<()> {
+        for child in &target.children {
+            match child {
+                TargetChild::Task(task) => {
+                    debug!("Executing task: {}", task.name);
+                    let outputs =
+                        match self
+                            .task_registry
+                            .execute_task(task, &self.model, &self.file_system)
+                        {
+                            Ok(outputs) => outputs,
+                            Err(error) => {
+                                // OnError targets can see that the last task failed
+                                self.model.set_reserved_property(
+                                    "MSBuildLastTaskResult",
+                                    "false".to_string(),
+                                );
+                                return Err(error);
+                            }
+                        };
+                    if let Some(outputs) = outputs {
+                        self.model
+                            .set_reserved_property("MSBuildLastTaskResult", "true".to_string());
+                        self.apply_task_outputs(task, &outputs)?;
+                    }
+                }
+                TargetChild::PropertyGroup(group) | TargetChild::ItemGroup(group) => {
+                    let mut parser = ProjectParser::with_file_system(self.file_system.clone());
+                    parser.apply_target_group(&mut self.model, group)?;
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// After a task of `target` failed, run the targets its `OnError` elements
+    /// name. The build fails regardless, so errors in them are only logged.
+    fn run_on_error_targets(&mut self, target: &Target, state: &mut BuildState) {
+        for on_error in &target.on_error {
+            let execute_targets = match self.on_error_targets(on_error) {
+                Ok(Some(execute_targets)) => execute_targets,
+                Ok(None) => continue,
+                Err(error) => {
+                    error!("{}: error: {error}", on_error.location);
+                    continue;
+                }
+            };
+            for name in execute_targets.split(';').map(str::trim) {
+                if name.is_empty() {
+                    continue;
+                }
+                info!("Running {name} after failure of target {}", target.name);
+                if let Err(error) = self.execute_target_recursive(name, state) {
+                    error!(
+                        "{}: error: OnError target {name} failed: {error}",
+                        on_error.location
+                    );
+                }
+            }
+        }
+    }
 
+    /// The expanded `ExecuteTargets` of an `OnError` element whose condition holds
+    fn on_error_targets(&self, on_error: &OnError) -> Result<Option<String>> {
+        let evaluator = ExpressionEvaluator::new(&self.model);
+        if let Some(condition) = &on_error.condition
+            && !evaluator.evaluate_condition(condition)?
+        {
+            return Ok(None);
+        }
+        evaluator.evaluate(&on_error.execute_targets).map(Some)
+    }
+
+    /// Store task outputs in the properties and items named by `<Output>` elements
+    fn apply_task_outputs(&mut self, task: &Task, outputs: &TaskOutputs) -> Result<()> {
+        for output in &task.outputs {
+            if let Some(condition) = &output.condition {
+                let evaluator = ExpressionEvaluator::new(&self.model);
+                if !evaluator.evaluate_condition(condition)? {
+                    continue;
+                }
+            }
+            let values = outputs.get(&output.task_parameter).unwrap_or_default();
+            if let Some(property_name) = &output.property_name {
+                self.model.set_property_at(
+                    property_name.clone(),
+                    values.join(";"),
+                    output.location.clone(),
+                )?;
+            }
+            if let Some(item_name) = &output.item_name {
+                for value in values {
+                    self.model.add_item(Item {
+                        item_type: item_name.clone(),
+                        name: value.clone(),
+                        metadata: HashMap::new(),
+                        location: output.location.clone(),
+                    });
+                }
+            }
+        }
         Ok(())
     }
 
@@ -150,10 +526,40 @@ impl ProjectEvaluator {
     }
 }
 
+/// The full list of an item type after a partial build. Up-to-date items are
+/// kept; each out-of-date item the target saw is replaced by what the target
+/// left of it, which is gone if it was removed; items it added come last.
+/// Targets remove items without reordering and append new ones, so what is
+/// left of the narrowed items comes first in `after`, in order.
+fn merge_partial_items(all: Vec<Item>, narrowed: &[Item], after: Vec<Item>) -> Vec<Item> {
+    let same = |a: &Item, b: &Item| a.name == b.name && a.metadata == b.metadata;
+    let mut narrowed = narrowed.iter().peekable();
+    let mut after = after.into_iter().peekable();
+    let mut merged = Vec::new();
+    for item in all {
+        if narrowed
+            .next_if(|out_of_date| same(out_of_date, &item))
+            .is_none()
+        {
+            merged.push(item);
+            continue;
+        }
+        if let Some(kept) = after.next_if(|kept| kept.name == item.name) {
+            merged.push(kept);
+        }
+    }
+    merged.extend(after);
+    merged
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::encoding::{BOM, TextEncoding};
+    use crate::file_system::InMemoryFileSystem;
+    use crate::sdk::LocalSdkResolver;
     use std::io::Write;
+    use std::time::{Duration, SystemTime};
     use tempfile::NamedTempFile;
 
     #[test]
@@ -184,7 +590,918 @@ mod tests {
 
         let mut evaluator = ProjectEvaluator::new();
         evaluator.load_project(temp_file.path())?;
-        evaluator.execute_target("Build")?;
+        evaluator.execute_target("Build").into_result()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_failure_reports_location() -> Result<()> {
+        let xml_content = r#"<Project>
+  <Target Name="Build">
+    <Error Text="Broken" />
+  </Target>
+</Project>"#;
+
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(xml_content.as_bytes())?;
+
+        let mut evaluator = ProjectEvaluator::new();
+        evaluator.load_project(temp_file.path())?;
+        let error = evaluator.execute_target("Build").into_result().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}(3,5): error: Build failed: Broken",
+                temp_file.path().display()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluation_on_in_memory_file_system() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file(
+                    "/repo/common.props",
+                    "<Project><PropertyGroup><Common>yes</Common></PropertyGroup></Project>",
+                )
+                .with_file("/repo/readme.txt", "hello"),
+        );
+
+        let mut evaluator = ProjectEvaluator::with_file_system(fs.clone());
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <Import Project="/repo/common.props" />
+  <ItemGroup>
+    <Content Include="readme.txt" />
+  </ItemGroup>
+  <Target Name="Build">
+    <Copy SourceFiles="@(Content)" DestinationFolder="out" />
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+        assert_eq!(
+            evaluator.get_model().get_property("Common"),
+            Some(&"yes".to_string())
+        );
+
+        evaluator.execute_target("Build").into_result()?;
+        assert_eq!(fs.read(Path::new("/repo/out/readme.txt"))?, b"hello");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_utf16_project_evaluates_like_utf8() -> Result<()> {
+        let content = r#"<?xml version="1.0" encoding="utf-16"?>
+<Project ToolsVersion="4.0" xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
+  <PropertyGroup>
+    <Greeting>Grüße</Greeting>
+  </PropertyGroup>
+  <ItemGroup>
+    <Compile Include="Program.cs" />
+  </ItemGroup>
+</Project>"#;
+        let utf16 = TextEncoding::Utf16Le.encode(&format!("{BOM}{content}"));
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file("/repo/utf8.proj", content)
+                .with_file("/repo/utf16.proj", utf16),
+        );
+
+        let mut models = Vec::new();
+        for file in ["/repo/utf8.proj", "/repo/utf16.proj"] {
+            let mut evaluator = ProjectEvaluator::with_file_system(fs.clone());
+            evaluator.load_project(file)?;
+            let model = evaluator.get_model();
+            models.push((
+                model.properties.clone(),
+                model.get_all_item_names("Compile"),
+            ));
+        }
+        assert_eq!(models[0], models[1]);
+        assert_eq!(models[1].0.get("Greeting"), Some(&"Grüße".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_imported_item_definitions_merge() -> Result<()> {
+        let fs = Arc::new(InMemoryFileSystem::new().with_file(
+            "/repo/defaults.props",
+            r#"<Project>
+  <ItemDefinitionGroup>
+    <Compile><Nullable>enable</Nullable></Compile>
+  </ItemDefinitionGroup>
+  <ItemGroup><Compile Include="imported.cs" /></ItemGroup>
+</Project>"#,
+        ));
+
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <ItemDefinitionGroup>
+    <Compile><Optimize>true</Optimize></Compile>
+  </ItemDefinitionGroup>
+  <ItemGroup><Compile Include="local.cs" /></ItemGroup>
+  <Import Project="/repo/defaults.props" />
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        let model = evaluator.get_model();
+        let definition = model.get_item_definition("Compile").unwrap();
+        assert_eq!(definition.get("Nullable"), Some(&"enable".to_string()));
+        for item in model.get_items("Compile").unwrap() {
+            assert_eq!(item.metadata.get("Nullable"), Some(&"enable".to_string()));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_groups_inside_targets_change_the_build() -> Result<()> {
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <ItemDefinitionGroup>
+    <Compile><Kind>source</Kind></Compile>
+  </ItemDefinitionGroup>
+  <ItemGroup>
+    <Compile Include="a.cs;c.cs" />
+    <Compile Include="b.cs" Kind="generated" />
+    <Generated Include="x.g.cs" Kind="generated" />
+  </ItemGroup>
+  <PropertyGroup><Unset>set</Unset></PropertyGroup>
+  <Target Name="Prepare">
+    <PropertyGroup>
+      <Stage>prepared</Stage>
+      <Unset></Unset>
+    </PropertyGroup>
+    <Error Text="Stage not visible" Condition="'$(Stage)' != 'prepared'" />
+    <ItemGroup>
+      <Compile Remove="a.cs" />
+      <Compile Condition="'%(Kind)' == 'source'">
+        <Checked>yes</Checked>
+      </Compile>
+      <Copied Include="@(Compile)" Extra="x" KeepMetadata="Kind" />
+      <Copied Include="@(Compile)" KeepMetadata="Kind" KeepDuplicates="false" />
+      <Stripped Include="@(Compile)" RemoveMetadata="Kind;Checked" />
+    </ItemGroup>
+  </Target>
+  <Target Name="Build" DependsOnTargets="Prepare">
+    <ItemGroup>
+      <Compile Remove="@(Generated)" MatchOnMetadata="Kind" />
+    </ItemGroup>
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+        evaluator.execute_target("Build").into_result()?;
+
+        let model = evaluator.get_model();
+        assert_eq!(model.get_property("Stage"), Some(&"prepared".to_string()));
+        assert_eq!(model.get_property("Unset"), Some(&String::new()));
+        assert_eq!(model.get_all_item_names("Compile"), "c.cs");
+        assert_eq!(
+            model.get_items("Compile").unwrap()[0]
+                .metadata
+                .get("Checked"),
+            Some(&"yes".to_string())
+        );
+        assert_eq!(model.get_all_item_names("Copied"), "c.cs;b.cs");
+        let copied = model.get_items("Copied").unwrap();
+        assert_eq!(copied[0].metadata.len(), 1);
+        assert_eq!(
+            copied[1].metadata.get("Kind"),
+            Some(&"generated".to_string())
+        );
+        let stripped = model.get_items("Stripped").unwrap();
+        assert!(stripped.iter().all(|item| item.metadata.is_empty()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_task_outputs_feed_properties_and_items() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file("/repo/a.txt", "a")
+                .with_file("/repo/b.txt", "b"),
+        );
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <Target Name="Build">
+    <Copy SourceFiles="a.txt;missing.txt;b.txt" DestinationFolder="out">
+      <Output TaskParameter="CopiedFiles" ItemName="Copied" />
+      <Output TaskParameter="DestinationFiles" PropertyName="Destinations" />
+      <Output TaskParameter="CopiedFiles" PropertyName="Skipped" Condition="false" />
+    </Copy>
+    <Copy SourceFiles="@(Copied)" DestinationFolder="again" Condition="false">
+      <Output TaskParameter="CopiedFiles" PropertyName="Skipped" />
+    </Copy>
+    <Error Text="Outputs not visible" Condition="'@(Copied)' != 'a.txt;b.txt'" />
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        evaluator.execute_target("Build").into_result()?;
+
+        let model = evaluator.get_model();
+        assert_eq!(model.get_all_item_names("Copied"), "a.txt;b.txt");
+        assert_eq!(
+            model.get_property("Destinations"),
+            Some(&"/repo/out/a.txt;/repo/out/b.txt".to_string())
+        );
+        assert_eq!(model.get_property("Skipped"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_on_error_runs_targets_and_build_still_fails() -> Result<()> {
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <Target Name="Build">
+    <Message Text="Starting" />
+    <Error Text="Broken" />
+    <PropertyGroup><AfterError>ran</AfterError></PropertyGroup>
+    <OnError ExecuteTargets="Cleanup;Report" />
+    <OnError ExecuteTargets="Skipped" Condition="false" />
+  </Target>
+  <Target Name="Cleanup">
+    <PropertyGroup><Cleaned>yes</Cleaned><LastResult>$(MSBuildLastTaskResult)</LastResult></PropertyGroup>
+  </Target>
+  <Target Name="Report">
+    <ItemGroup><Reported Include="Build" /></ItemGroup>
+  </Target>
+  <Target Name="Skipped">
+    <PropertyGroup><Skipped>yes</Skipped></PropertyGroup>
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        let error = evaluator.execute_target("Build").into_result().unwrap_err();
+        assert!(error.to_string().ends_with("error: Build failed: Broken"));
+
+        let model = evaluator.get_model();
+        assert_eq!(model.get_property("Cleaned"), Some(&"yes".to_string()));
+        assert_eq!(model.get_property("LastResult"), Some(&"false".to_string()));
+        assert_eq!(model.get_all_item_names("Reported"), "Build");
+        assert_eq!(model.get_property("Skipped"), None);
+        assert_eq!(model.get_property("AfterError"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_before_and_after_targets_order() -> Result<()> {
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <Target Name="Build" DependsOnTargets="Compile;Optional">
+    <ItemGroup><Order Include="Build" /></ItemGroup>
+  </Target>
+  <Target Name="Compile">
+    <ItemGroup><Order Include="Compile" /></ItemGroup>
+  </Target>
+  <Target Name="Optional" Condition="false" DependsOnTargets="Never">
+    <ItemGroup><Order Include="Optional" /></ItemGroup>
+  </Target>
+  <Target Name="Never">
+    <ItemGroup><Order Include="Never" /></ItemGroup>
+  </Target>
+  <Target Name="PreBuild" BeforeTargets="Build">
+    <ItemGroup><Order Include="PreBuild" /></ItemGroup>
+  </Target>
+  <Target Name="PostBuild" AfterTargets="Build">
+    <ItemGroup><Order Include="PostBuild" /></ItemGroup>
+  </Target>
+  <Target Name="PreBuild2" BeforeTargets="Build;Compile">
+    <ItemGroup><Order Include="PreBuild2" /></ItemGroup>
+  </Target>
+  <Target Name="AroundOptional" BeforeTargets="Optional" AfterTargets="Optional">
+    <ItemGroup><Order Include="AroundOptional" /></ItemGroup>
+  </Target>
+  <Target Name="AfterOptional" AfterTargets="Optional">
+    <ItemGroup><Order Include="AfterOptional" /></ItemGroup>
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        evaluator.execute_target("Build").into_result()?;
+
+        assert_eq!(
+            evaluator.get_model().get_all_item_names("Order"),
+            "PreBuild2;Compile;AroundOptional;AfterOptional;PreBuild;Build;PostBuild"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_circular_dependencies_are_errors() -> Result<()> {
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <Target Name="Build" DependsOnTargets="Compile" />
+  <Target Name="Compile" />
+  <Target Name="Prep" BeforeTargets="Build" DependsOnTargets="Build" />
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        let error = evaluator.execute_target("Build").into_result().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "/repo/app.proj(4,3): error: There is a circular dependency in the target dependency graph: Build -> Prep -> Build"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_incremental_targets_skip_and_build_partially() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file("/repo/a.cs", "")
+                .with_file("/repo/b.cs", "")
+                .with_file("/repo/obj/a.obj", "")
+                .with_file("/repo/obj/b.obj", ""),
+        );
+        let time = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        fs.set_modified("/repo/a.cs", time(10));
+        fs.set_modified("/repo/obj/a.obj", time(20));
+        fs.set_modified("/repo/obj/b.obj", time(20));
+        fs.set_modified("/repo/b.cs", time(30));
+
+        let project = r#"<Project>
+  <ItemGroup>
+    <Src Include="a.cs;b.cs" />
+  </ItemGroup>
+  <Target Name="Build" Inputs="@(Src)" Outputs="@(Src->'obj/%(Filename).obj')">
+    <ItemGroup><Built Include="@(Src)" /></ItemGroup>
+  </Target>
+</Project>"#;
+        let build = || -> Result<String> {
+            let mut evaluator = ProjectEvaluator::with_file_system(fs.clone());
+            evaluator.load_project_from_str(project, "/repo/app.proj")?;
+            evaluator.execute_target("Build").into_result()?;
+            assert_eq!(evaluator.get_model().get_all_item_names("Src"), "a.cs;b.cs");
+            Ok(evaluator.get_model().get_all_item_names("Built"))
+        };
+
+        assert_eq!(build()?, "b.cs");
+        fs.set_modified("/repo/obj/b.obj", time(40));
+        assert_eq!(build()?, "");
+        fs.set_modified("/repo/a.cs", time(50));
+        fs.set_modified("/repo/b.cs", time(50));
+        assert_eq!(build()?, "a.cs;b.cs");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_partial_build_keeps_item_changes() -> Result<()> {
+        let fs = Arc::new(InMemoryFileSystem::new());
+        let time = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        for (file, seconds) in [("a", 10), ("b", 30), ("c", 30), ("d", 10)] {
+            fs.add_file(format!("/repo/{file}.cs"), "");
+            fs.add_file(format!("/repo/obj/{file}.obj"), "");
+            fs.set_modified(format!("/repo/{file}.cs"), time(seconds));
+            fs.set_modified(format!("/repo/obj/{file}.obj"), time(20));
+        }
+
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <ItemGroup>
+    <Src Include="a.cs;b.cs;c.cs;d.cs" />
+  </ItemGroup>
+  <Target Name="Build" Inputs="@(Src)" Outputs="@(Src->'obj/%(Filename).obj')">
+    <ItemGroup>
+      <Src Remove="b.cs" />
+      <Src Built="true" />
+      <Src Include="new.cs" />
+    </ItemGroup>
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+        evaluator.execute_target("Build").into_result()?;
+
+        let src = evaluator.get_model().get_items("Src").unwrap();
+        let names: Vec<_> = src.iter().map(|item| item.name.as_str()).collect();
+        assert_eq!(names, ["a.cs", "c.cs", "d.cs", "new.cs"]);
+        let built: Vec<_> = src
+            .iter()
+            .map(|item| item.metadata.get("Built").map(String::as_str))
+            .collect();
+        // The update ran before new.cs was added
+        assert_eq!(built, [None, Some("true"), None, None]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_build_result_reports_outcomes_and_returned_items() -> Result<()> {
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <ItemGroup>
+    <Compile Include="a.cs" Kind="code" />
+  </ItemGroup>
+  <Target Name="Build" DependsOnTargets="Compile;Optional" Returns="@(Compile->'%(Filename).dll');extra.txt" />
+  <Target Name="Compile" Outputs="@(Compile)" />
+  <Target Name="Optional" Condition="false" />
+  <Target Name="Broken" DependsOnTargets="Fail" />
+  <Target Name="Fail">
+    <Error Text="Broken" />
+  </Target>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        let result = evaluator.execute_target("Build");
+        assert!(result.is_success());
+        let outcomes: Vec<_> = result
+            .targets
+            .iter()
+            .map(|(name, target)| (name.as_str(), target.outcome.clone()))
+            .collect();
+        assert_eq!(
+            outcomes,
+            [
+                ("Compile", TargetOutcome::Success),
+                (
+                    "Optional",
+                    TargetOutcome::Skipped("Condition 'false' was false.".to_string())
+                ),
+                ("Build", TargetOutcome::Success),
+            ]
+        );
+        let build = result.get_target_result("Build").unwrap();
+        let returned: Vec<_> = build
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.item_type.as_str(),
+                    item.name.as_str(),
+                    item.metadata.get("Kind"),
+                )
+            })
+            .collect();
+        assert_eq!(
+            returned,
+            [
+                ("Compile", "a.dll", Some(&"code".to_string())),
+                ("", "extra.txt", None)
+            ]
+        );
+        assert_eq!(
+            result.get_target_result("Compile").unwrap().items[0].name,
+            "a.cs"
+        );
+
+        let result = evaluator.execute_target("Broken");
+        assert!(!result.is_success());
+        for name in ["Fail", "Broken"] {
+            let outcome = &result.get_target_result(name).unwrap().outcome;
+            assert!(matches!(outcome, TargetOutcome::Failure(error) if error.ends_with("Broken")));
+        }
+        assert!(result.into_result().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_default_and_initial_targets_merge_across_imports() -> Result<()> {
+        let fs = Arc::new(InMemoryFileSystem::new().with_file(
+            "/repo/common.targets",
+            r#"<Project DefaultTargets="Test" InitialTargets="Restore" TreatAsLocalProperty="OutDir">
+  <Target Name="Restore" />
+  <Target Name="Test" />
+</Project>"#,
+        ));
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.load_project_from_str(
+            r#"<Project InitialTargets="$(Check)" TreatAsLocalProperty="Configuration">
+  <PropertyGroup>
+    <Check>Validate</Check>
+  </PropertyGroup>
+  <Import Project="/repo/common.targets" />
+  <Target Name="Build" />
+  <Target Name="Validate" />
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        assert_eq!(evaluator.default_targets()?, ["Test"]);
+        assert_eq!(
+            evaluator.get_model().treat_as_local_properties,
+            ["Configuration", "OutDir"]
+        );
+        let result = evaluator.execute_target("Build").into_result()?;
+        let order: Vec<_> = result.targets.keys().map(String::as_str).collect();
+        assert_eq!(order, ["Validate", "Restore", "Build"]);
+
+        // Without DefaultTargets, the first target is the default
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.load_project_from_str(
+            r#"<Project><Target Name="Pack" /></Project>"#,
+            "/repo/b.proj",
+        )?;
+        assert_eq!(evaluator.default_targets()?, ["Pack"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_project_sdks_import_props_and_targets() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file(
+                    "/sdks/My.Sdk/Sdk/Sdk.props",
+                    r#"<Project>
+  <PropertyGroup>
+    <Configuration>Debug</Configuration>
+    <FromProps>yes</FromProps>
+  </PropertyGroup>
+</Project>"#,
+                )
+                .with_file(
+                    "/sdks/My.Sdk/Sdk/Sdk.targets",
+                    r#"<Project>
+  <Target Name="Build">
+    <PropertyGroup><Built>$(Configuration)</Built></PropertyGroup>
+  </Target>
+</Project>"#,
+                )
+                .with_file(
+                    "/sdks/Extra.Sdk/Sdk/Extra.props",
+                    r#"<Project><PropertyGroup><Extra>yes</Extra></PropertyGroup></Project>"#,
+                ),
+        );
+        let mut evaluator = ProjectEvaluator::with_file_system(fs.clone());
+        evaluator.set_sdk_resolver(Arc::new(LocalSdkResolver::new(vec![PathBuf::from(
+            "/sdks",
+        )])));
+        evaluator.load_project_from_str(
+            r#"<Project Sdk="My.Sdk">
+  <PropertyGroup>
+    <Configuration>Release</Configuration>
+  </PropertyGroup>
+  <Import Project="Extra.props" Sdk="Extra.Sdk" />
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+        evaluator.execute_target("Build").into_result()?;
+
+        let model = evaluator.get_model();
+        assert_eq!(model.get_property("FromProps"), Some(&"yes".to_string()));
+        assert_eq!(model.get_property("Extra"), Some(&"yes".to_string()));
+        assert_eq!(model.get_property("Built"), Some(&"Release".to_string()));
+
+        // The default resolver searches MSBuildSDKsPath from the given environment
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.set_environment([("MSBuildSDKsPath".to_string(), "/sdks".to_string())]);
+        let missing = r#"<Project>
+  <Sdk Name="My.Sdk" Version="1.0" />
+</Project>"#;
+        let error = evaluator
+            .load_project_from_str(missing, "/repo/app.proj")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "/repo/app.proj(1,1): error: Could not resolve SDK \"My.Sdk/1.0\". Searched:\n  /sdks/My.Sdk/1.0/Sdk"
+        );
+        evaluator.set_environment([]);
+        let error = evaluator
+            .load_project_from_str(missing, "/repo/app.proj")
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("no SDK directories are configured; set MSBuildSDKsPath")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_imports_are_positional_relative_and_cycle_safe() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file(
+                    "/repo/build/common.props",
+                    r#"<Project>
+  <PropertyGroup>
+    <Color>red</Color>
+    <Size>small</Size>
+  </PropertyGroup>
+  <Import Project="nested/*.props" />
+  <Import Project="../app.proj" />
+</Project>"#,
+                )
+                .with_file(
+                    "/repo/build/grouped.props",
+                    r#"<Project><PropertyGroup><Grouped>yes</Grouped></PropertyGroup></Project>"#,
+                )
+                .with_file(
+                    "/repo/build/nested/a.props",
+                    r#"<Project><ItemGroup><Order Include="a" /></ItemGroup></Project>"#,
+                )
+                .with_file(
+                    "/repo/build/nested/b.props",
+                    r#"<Project>
+  <ItemGroup><Order Include="b" /></ItemGroup>
+  <Import Project="a.props" />
+</Project>"#,
+                ),
+        );
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <PropertyGroup>
+    <Color>blue</Color>
+  </PropertyGroup>
+  <Import Project="build/common.props" />
+  <Import Project="build/common.props" />
+  <PropertyGroup>
+    <Size>large</Size>
+  </PropertyGroup>
+  <Import Project="missing.props" Condition="'$(Size)' == 'small'" />
+  <ImportGroup Condition="'$(Size)' == 'large'">
+    <Import Project="build/grouped.props" />
+  </ImportGroup>
+  <ImportGroup Condition="'$(Size)' == 'small'">
+    <Import Project="missing.props" />
+  </ImportGroup>
+</Project>"#,
+            "/repo/app.proj",
+        )?;
+
+        let model = evaluator.get_model();
+        // Each import overrides what came before it and is overridden by what follows
+        assert_eq!(model.get_property("Color"), Some(&"red".to_string()));
+        assert_eq!(model.get_property("Size"), Some(&"large".to_string()));
+        // Wildcards import in name order; a.props is imported only once
+        assert_eq!(model.get_all_item_names("Order"), "a;b");
+        assert_eq!(model.get_property("Grouped"), Some(&"yes".to_string()));
+        assert_eq!(model.imports.len(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_directory_build_files_are_discovered() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file(
+                    "/repo/Directory.Build.props",
+                    r#"<Project><PropertyGroup><Shared>root</Shared></PropertyGroup></Project>"#,
+                )
+                .with_file(
+                    "/repo/Directory.Build.targets",
+                    r#"<Project><Target Name="RootTargets" /></Project>"#,
+                )
+                .with_file(
+                    "/repo/src/Directory.Build.targets",
+                    r#"<Project><Target Name="SrcTargets" /></Project>"#,
+                )
+                .with_file(
+                    "/repo/src/custom.targets",
+                    r#"<Project><Target Name="CustomTargets" /></Project>"#,
+                )
+                .with_file(
+                    "/sdks/Hermetic.Sdk/Sdk/Sdk.props",
+                    r#"<Project>
+  <PropertyGroup>
+    <ImportDirectoryBuildProps>false</ImportDirectoryBuildProps>
+    <DirectoryBuildTargetsPath>../custom.targets</DirectoryBuildTargetsPath>
+  </PropertyGroup>
+</Project>"#,
+                )
+                .with_file(
+                    "/sdks/Layered.Sdk/Sdk/Sdk.props",
+                    r#"<Project><PropertyGroup><Shared>sdk</Shared></PropertyGroup></Project>"#,
+                ),
+        );
+        let load = |project: &str| -> Result<ProjectEvaluator> {
+            let mut evaluator = ProjectEvaluator::with_file_system(fs.clone());
+            evaluator.set_sdk_resolver(Arc::new(LocalSdkResolver::new(vec![PathBuf::from(
+                "/sdks",
+            )])));
+            evaluator.load_project_from_str(project, "/repo/src/app/app.proj")?;
+            Ok(evaluator)
+        };
+
+        let evaluator = load(
+            r#"<Project>
+  <PropertyGroup><Seen>$(Shared)</Seen></PropertyGroup>
+</Project>"#,
+        )?;
+        let model = evaluator.get_model();
+        assert_eq!(model.get_property("Shared"), Some(&"root".to_string()));
+        assert_eq!(
+            model.get_property("DirectoryBuildPropsPath"),
+            Some(&"/repo/Directory.Build.props".to_string())
+        );
+        let targets: Vec<_> = model.targets.keys().map(String::as_str).collect();
+        assert_eq!(targets, ["SrcTargets"]);
+
+        let evaluator = load(
+            r#"<Project>
+  <PropertyGroup><ImportDirectoryBuildTargets>false</ImportDirectoryBuildTargets></PropertyGroup>
+</Project>"#,
+        )?;
+        assert!(evaluator.get_model().targets.is_empty());
+
+        let evaluator = load(r#"<Project Sdk="Hermetic.Sdk" />"#)?;
+        let model = evaluator.get_model();
+        assert_eq!(model.get_property("Shared"), None);
+        let targets: Vec<_> = model.targets.keys().map(String::as_str).collect();
+        assert_eq!(targets, ["CustomTargets"]);
+
+        // Directory.Build.props comes after Sdk.props, so it overrides SDK defaults
+        let evaluator = load(r#"<Project Sdk="Layered.Sdk" />"#)?;
+        assert_eq!(
+            evaluator.get_model().get_property("Shared"),
+            Some(&"root".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_project_finds_files_above_current_directory() -> Result<()> {
+        let current = std::env::current_dir()?;
+        let parent = current.parent().unwrap_or(&current);
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file(
+                    parent.join("Directory.Build.props"),
+                    r#"<Project><PropertyGroup><Shared>root</Shared></PropertyGroup></Project>"#,
+                )
+                .with_file(
+                    parent.join("global.json"),
+                    r#"{ "msbuild-sdks": { "My.Sdk": "2.0.0" } }"#,
+                )
+                .with_file(
+                    "/sdks/My.Sdk/2.0.0/Sdk/Sdk.props",
+                    r#"<Project><PropertyGroup><SdkVersion>2.0.0</SdkVersion></PropertyGroup></Project>"#,
+                ),
+        );
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        evaluator.set_sdk_resolver(Arc::new(LocalSdkResolver::new(vec![PathBuf::from(
+            "/sdks",
+        )])));
+        evaluator.load_project_from_str(r#"<Project Sdk="My.Sdk" />"#, "app.proj")?;
+
+        let model = evaluator.get_model();
+        assert_eq!(model.get_property("Shared"), Some(&"root".to_string()));
+        assert_eq!(model.get_property("SdkVersion"), Some(&"2.0.0".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reserved_properties() -> Result<()> {
+        let fs = Arc::new(InMemoryFileSystem::new().with_file(
+            "/repo/build/common.props",
+            r#"<Project>
+  <PropertyGroup>
+    <ImportDir>$(MSBuildThisFileDirectory)</ImportDir>
+    <ImportFile>$(MSBuildThisFile)</ImportFile>
+  </PropertyGroup>
+  <Target Name="Tool" Condition="'$(MSBuildThisFile)' == 'common.props'">
+    <PropertyGroup><ToolDir>$(MSBuildThisFileDirectory)</ToolDir></PropertyGroup>
+  </Target>
+</Project>"#,
+        ));
+        let mut evaluator = ProjectEvaluator::with_file_system(fs.clone());
+        evaluator.load_project_from_str(
+            r#"<Project>
+  <Import Project="build/common.props" />
+  <Target Name="Build" DependsOnTargets="Tool">
+    <PropertyGroup><BuildDir>$(MSBuildThisFileDirectory)</BuildDir></PropertyGroup>
+  </Target>
+  <PropertyGroup>
+    <ProjectDir>$(MSBuildThisFileDirectory)</ProjectDir>
+    <Output>$(MSBuildProjectDirectory)/bin/$(MSBuildProjectName)$(MSBuildProjectExtension)</Output>
+  </PropertyGroup>
+</Project>"#,
+            "/repo/app.csproj",
+        )?;
+
+        let model = evaluator.get_model();
+        let property = |name| model.get_property(name).map(String::as_str);
+        assert_eq!(property("ImportDir"), Some("/repo/build/"));
+        assert_eq!(property("ImportFile"), Some("common.props"));
+        assert_eq!(property("ProjectDir"), Some("/repo/"));
+        let output = ExpressionEvaluator::new(model).evaluate("$(Output)")?;
+        assert_eq!(output, "/repo/bin/app.csproj");
+        assert_eq!(property("MSBuildToolsVersion"), Some("Current"));
+
+        // Targets see the file that defines them
+        evaluator.execute_target("Build").into_result()?;
+        let model = evaluator.get_model();
+        let property = |name| model.get_property(name).map(String::as_str);
+        assert_eq!(property("ToolDir"), Some("/repo/build/"));
+        assert_eq!(property("BuildDir"), Some("/repo/"));
+        assert_eq!(property("MSBuildThisFile"), Some("app.csproj"));
+
+        let mut evaluator = ProjectEvaluator::with_file_system(fs);
+        let error = evaluator
+            .load_project_from_str(
+                r#"<Project>
+  <PropertyGroup>
+    <MSBuildProjectName>Other</MSBuildProjectName>
+  </PropertyGroup>
+</Project>"#,
+                "/repo/app.csproj",
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "/repo/app.csproj(3,5): error: The reserved property \"MSBuildProjectName\" cannot be modified"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_environment_variables_are_properties() -> Result<()> {
+        let project = r#"<Project>
+  <PropertyGroup>
+    <Configuration>Release</Configuration>
+    <Cache>$(HOME)/.cache</Cache>
+  </PropertyGroup>
+</Project>"#;
+        let variables = [("HOME", "/home/me"), ("Configuration", "Debug")]
+            .map(|(name, value)| (name.to_string(), value.to_string()));
+
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.set_environment(variables);
+        evaluator.load_project_from_str(project, "/repo/app.proj")?;
+        let evaluate = |evaluator: &ProjectEvaluator, input| {
+            ExpressionEvaluator::new(evaluator.get_model()).evaluate(input)
+        };
+        assert_eq!(evaluate(&evaluator, "$(Configuration)")?, "Release");
+        assert_eq!(evaluate(&evaluator, "$(Cache)")?, "/home/me/.cache");
+
+        evaluator.set_environment([]);
+        evaluator.load_project_from_str(project, "/repo/app.proj")?;
+        assert_eq!(evaluate(&evaluator, "$(Cache)")?, "/.cache");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_global_properties_override_project_values() -> Result<()> {
+        let project = r#"<Project TreatAsLocalProperty="OutDir">
+  <PropertyGroup>
+    <Seen>$(Configuration)</Seen>
+    <Configuration>Debug</Configuration>
+    <OutDir>bin/$(Configuration)</OutDir>
+    <Cache>$(HOME)</Cache>
+  </PropertyGroup>
+</Project>"#;
+        let mut evaluator = ProjectEvaluator::with_file_system(Arc::new(InMemoryFileSystem::new()));
+        evaluator.set_environment([("HOME".to_string(), "/home/me".to_string())]);
+        evaluator.set_global_properties(
+            [
+                ("Configuration", "Release"),
+                ("OutDir", "out"),
+                ("HOME", "/global"),
+            ]
+            .map(|(name, value)| (name.to_string(), value.to_string())),
+        );
+        evaluator.load_project_from_str(project, "/repo/app.proj")?;
+        let evaluate = |input| ExpressionEvaluator::new(evaluator.get_model()).evaluate(input);
+
+        assert_eq!(evaluate("$(Seen)")?, "Release");
+        assert_eq!(evaluate("$(Configuration)")?, "Release");
+        assert_eq!(evaluate("$(OutDir)")?, "bin/Release");
+        assert_eq!(evaluate("$(Cache)")?, "/global");
+
+        // Writes that bypass the checks cannot hide a global value either
+        evaluator
+            .model
+            .set_property("Configuration".to_string(), "Debug".to_string());
+        assert_eq!(
+            evaluator.get_model().get_property("Configuration"),
+            Some(&"Release".to_string())
+        );
 
         Ok(())
     }
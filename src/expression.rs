@@ -1,47 +1,107 @@
 use anyhow::Result;
-use regex::Regex;
+use regex::{Captures, Regex};
+use std::collections::HashMap;
 
-use crate::object_model::ProjectModel;
+use crate::object_model::{Item, ProjectModel};
 
 pub struct ExpressionEvaluator<'a> {
     model: &'a ProjectModel,
+    item: Option<&'a Item>,
 }
 
 impl<'a> ExpressionEvaluator<'a> {
     pub fn new(model: &'a ProjectModel) -> Self {
-        Self { model }
+        Self { model, item: None }
+    }
+
+    /// Resolve `%(Name)` references against the item being defined, including
+    /// well-known metadata such as `%(Filename)`
+    pub fn with_item(mut self, item: &'a Item) -> Self {
+        self.item = Some(item);
+        self
     }
 
     /// Evaluate a string that may contain property and item references
     pub fn evaluate(&self, input: &str) -> Result<String> {
-        let mut result = input.to_string();
-
-        // Replace property references $(PropertyName)
-        let prop_regex = Regex::new(r"\$\(([^)]+)\)").unwrap();
-        while let Some(captures) = prop_regex.captures(&result) {
-            let full_match = &captures[0];
-            let prop_name = &captures[1];
-
-            let replacement = self
-                .model
-                .get_property(prop_name)
-                .cloned()
-                .unwrap_or_default();
-
-            result = result.replace(full_match, &replacement);
+        let mut result = self.expand_properties(input);
+
+        // Replace item references @(ItemType), @(ItemType->'%(Transform)') and
+        // @(ItemType, 'separator') before metadata, which transforms contain
+        let item_regex =
+            Regex::new(r"@\(\s*([A-Za-z_][\w.-]*)\s*(?:->\s*'([^']*)'\s*)?(?:,\s*'([^']*)'\s*)?\)")
+                .unwrap();
+        result = item_regex
+            .replace_all(&result, |captures: &Captures| {
+                let items = self
+                    .model
+                    .get_items(&captures[1])
+                    .map_or(&[][..], Vec::as_slice);
+                let separator = captures.get(3).map_or(";", |m| m.as_str());
+                items
+                    .iter()
+                    .map(|item| match captures.get(2) {
+                        Some(template) => transform_item(item, template.as_str()),
+                        None => item.name.clone(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join(separator)
+            })
+            .into_owned();
+
+        // Replace metadata references %(Name) or %(ItemType.Name)
+        if let Some(item) = self.item {
+            result = transform_item(item, &result);
         }
 
-        // Replace item references @(ItemType)
-        let item_regex = Regex::new(r"@\(([^)]+)\)").unwrap();
-        while let Some(captures) = item_regex.captures(&result) {
-            let full_match = &captures[0];
-            let item_type = &captures[1];
+        Ok(result)
+    }
 
-            let replacement = self.model.get_all_item_names(item_type);
-            result = result.replace(full_match, &replacement);
-        }
+    /// Replace `$(Name)` references with property values, leaving item and
+    /// metadata references for later. Values are substituted once, so a
+    /// property defined in terms of itself appends to its previous value.
+    pub fn expand_properties(&self, input: &str) -> String {
+        let prop_regex = Regex::new(r"\$\(([^)]+)\)").unwrap();
+        prop_regex
+            .replace_all(input, |captures: &Captures| {
+                self.model
+                    .get_property(captures[1].trim())
+                    .cloned()
+                    .unwrap_or_default()
+            })
+            .into_owned()
+    }
 
-        Ok(result)
+    /// Evaluate a list of items. Items that come from an `@(Type)` reference or a
+    /// transform of one keep their metadata; other values have none.
+    pub fn evaluate_items(&self, input: &str) -> Result<Vec<(String, HashMap<String, String>)>> {
+        let reference_regex =
+            Regex::new(r"^@\(\s*([A-Za-z_][\w.-]*)\s*(?:->\s*'([^']*)'\s*)?\)$").unwrap();
+        let mut items = Vec::new();
+        for part in input.split(';').map(str::trim) {
+            let Some(captures) = reference_regex.captures(part) else {
+                let evaluated = self.evaluate(part)?;
+                for name in evaluated.split(';').map(str::trim) {
+                    if !name.is_empty() {
+                        items.push((name.to_string(), HashMap::new()));
+                    }
+                }
+                continue;
+            };
+            let template = match captures.get(2) {
+                Some(template) => Some(self.evaluate(template.as_str())?),
+                None => None,
+            };
+            for item in self.model.get_items(&captures[1]).into_iter().flatten() {
+                let name = match &template {
+                    Some(template) => transform_item(item, template),
+                    None => item.name.clone(),
+                };
+                if !name.is_empty() {
+                    items.push((name, item.metadata.clone()));
+                }
+            }
+        }
+        Ok(items)
     }
 
     /// Evaluate a condition expression
@@ -97,10 +157,32 @@ impl<'a> ExpressionEvaluator<'a> {
     }
 }
 
+fn metadata_regex() -> Regex {
+    Regex::new(r"%\(([^)]+)\)").unwrap()
+}
+
+/// Apply an item transform such as `obj/%(Filename).o` to one item. A
+/// qualified reference such as `%(Other.Filename)` is empty for items of
+/// another type.
+pub fn transform_item(item: &Item, template: &str) -> String {
+    metadata_regex()
+        .replace_all(template, |captures: &Captures| {
+            let name = &captures[1];
+            match name.rsplit_once('.') {
+                Some((item_type, _)) if !item_type.eq_ignore_ascii_case(&item.item_type) => {
+                    String::new()
+                }
+                Some((_, name)) => item.metadata_value(name).unwrap_or_default(),
+                None => item.metadata_value(name).unwrap_or_default(),
+            }
+        })
+        .into_owned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::object_model::{Item, ProjectModel};
+    use crate::object_model::{Item, ProjectModel, SourceLocation};
     use std::collections::HashMap;
 
     #[test]
@@ -125,12 +207,14 @@ mod tests {
             item_type: "Compile".to_string(),
             name: "file1.cs".to_string(),
             metadata: HashMap::new(),
+            location: SourceLocation::default(),
         };
 
         let item2 = Item {
             item_type: "Compile".to_string(),
             name: "file2.cs".to_string(),
             metadata: HashMap::new(),
+            location: SourceLocation::default(),
         };
 
         model.add_item(item1);
@@ -162,4 +246,93 @@ mod tests {
         assert!(!evaluator.evaluate_condition("").unwrap());
         assert!(evaluator.evaluate_condition("true").unwrap());
     }
+
+    #[test]
+    fn test_item_transforms() -> Result<()> {
+        let mut model = ProjectModel::new();
+        for name in ["src/a.cs", "b.cs"] {
+            model.add_item(Item {
+                item_type: "Compile".to_string(),
+                name: name.to_string(),
+                metadata: HashMap::from([("Kind".to_string(), "code".to_string())]),
+                location: SourceLocation::default(),
+            });
+        }
+        model.set_property("Out".to_string(), "obj".to_string());
+
+        let evaluator = ExpressionEvaluator::new(&model);
+        assert_eq!(
+            evaluator.evaluate("@(Compile->'$(Out)/%(Filename).o')")?,
+            "obj/a.o;obj/b.o"
+        );
+        assert_eq!(
+            evaluator.evaluate("@(Compile -> '%(RelativeDir)%(Kind)%(Extension)', ' ')")?,
+            "src/code.cs code.cs"
+        );
+        assert_eq!(
+            evaluator.evaluate("@(Compile, ',')|@(None)")?,
+            "src/a.cs,b.cs|"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_items_keeps_metadata() -> Result<()> {
+        let mut model = ProjectModel::new();
+        model.add_item(Item {
+            item_type: "Compile".to_string(),
+            name: "a.cs".to_string(),
+            metadata: HashMap::from([("Kind".to_string(), "code".to_string())]),
+            location: SourceLocation::default(),
+        });
+        model.set_property("Extra".to_string(), "x.txt;y.txt".to_string());
+
+        let evaluator = ExpressionEvaluator::new(&model);
+        let items = evaluator.evaluate_items("@(Compile);@(Compile->'%(Filename).o');$(Extra)")?;
+        let kind = HashMap::from([("Kind".to_string(), "code".to_string())]);
+        assert_eq!(
+            items,
+            [
+                ("a.cs".to_string(), kind.clone()),
+                ("a.o".to_string(), kind),
+                ("x.txt".to_string(), HashMap::new()),
+                ("y.txt".to_string(), HashMap::new()),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_substitution() -> Result<()> {
+        let model = ProjectModel::new();
+        let item = Item {
+            item_type: "Compile".to_string(),
+            name: "src/a.cs".to_string(),
+            metadata: HashMap::from([("Link".to_string(), "src/%(Link)".to_string())]),
+            location: SourceLocation::default(),
+        };
+
+        let evaluator = ExpressionEvaluator::new(&model).with_item(&item);
+        assert_eq!(
+            evaluator.evaluate("%(Link)|%(Compile.Link)|%(Missing)")?,
+            "src/%(Link)|src/%(Link)|"
+        );
+        assert_eq!(
+            evaluator.evaluate("linked/%(Filename)%(Extension)")?,
+            "linked/a.cs"
+        );
+        assert_eq!(
+            evaluator.evaluate("%(compile.Filename)|%(Other.Filename)")?,
+            "a|"
+        );
+        // Without an item in scope, metadata references are left for later
+        assert_eq!(
+            ExpressionEvaluator::new(&model).evaluate("%(Link)")?,
+            "%(Link)"
+        );
+
+        Ok(())
+    }
 }
@@ -0,0 +1,283 @@
+//! File access used by evaluation and tasks.
+//!
+//! Everything that touches disk goes through [`FileSystem`], so projects can be
+//! evaluated from editor buffers or generated content held in an
+//! [`InMemoryFileSystem`] as easily as from the real disk.
+
+use anyhow::{Result, anyhow};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// An entry of a directory listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DirEntry {
+    pub name: String,
+    pub is_dir: bool,
+}
+
+pub trait FileSystem: Send + Sync {
+    fn read(&self, path: &Path) -> Result<Vec<u8>>;
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()>;
+    fn exists(&self, path: &Path) -> bool;
+    fn create_dir_all(&self, path: &Path) -> Result<()>;
+    /// The entries of a directory, sorted by name
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>>;
+    /// When a file was last written, for up-to-date checks
+    fn modified(&self, path: &Path) -> Result<SystemTime>;
+
+    #[allow(dead_code)] // Public API method for library users
+    fn read_to_string(&self, path: &Path) -> Result<String> {
+        let bytes = self.read(path)?;
+        String::from_utf8(bytes).map_err(|e| anyhow!("{}: {e}", path.display()))
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        let contents = self.read(from)?;
+        self.write(to, &contents)
+    }
+}
+
+/// The real disk, through `std::fs`
+#[derive(Debug, Default, Clone, Copy)]
+pub struct PhysicalFileSystem;
+
+impl PhysicalFileSystem {
+    pub fn shared() -> Arc<dyn FileSystem> {
+        Arc::new(PhysicalFileSystem)
+    }
+}
+
+impl FileSystem for PhysicalFileSystem {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        std::fs::read(path).map_err(|e| anyhow!("{}: {e}", path.display()))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        std::fs::write(path, contents).map_err(|e| anyhow!("{}: {e}", path.display()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        path.exists()
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        std::fs::create_dir_all(path).map_err(|e| anyhow!("{}: {e}", path.display()))
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>> {
+        let error = |e: std::io::Error| anyhow!("{}: {e}", path.display());
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(path).map_err(error)? {
+            let entry = entry.map_err(error)?;
+            entries.push(DirEntry {
+                name: entry.file_name().to_string_lossy().into_owned(),
+                is_dir: entry.path().is_dir(),
+            });
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|e| anyhow!("{}: {e}", path.display()))
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> Result<()> {
+        std::fs::copy(from, to)
+            .map(|_| ())
+            .map_err(|e| anyhow!("{} -> {}: {e}", from.display(), to.display()))
+    }
+}
+
+/// Files held in memory, keyed by lexically normalized path
+#[allow(dead_code)] // Public API for library users and tests
+#[derive(Debug, Default)]
+pub struct InMemoryFileSystem {
+    files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+    directories: Mutex<BTreeSet<PathBuf>>,
+    modified: Mutex<BTreeMap<PathBuf, SystemTime>>,
+}
+
+#[allow(dead_code)] // Public API for library users and tests
+impl InMemoryFileSystem {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, creating its parent directories
+    pub fn with_file(self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) -> Self {
+        self.add_file(path, contents);
+        self
+    }
+
+    pub fn add_file(&self, path: impl AsRef<Path>, contents: impl Into<Vec<u8>>) {
+        let path = normalize(path.as_ref());
+        if let Some(parent) = path.parent() {
+            self.add_directories(parent);
+        }
+        self.touch(&path);
+        self.files.lock().unwrap().insert(path, contents.into());
+    }
+
+    /// Change the modification time of a file, such as to make it out of date
+    pub fn set_modified(&self, path: impl AsRef<Path>, time: SystemTime) {
+        let path = normalize(path.as_ref());
+        self.modified.lock().unwrap().insert(path, time);
+    }
+
+    fn touch(&self, path: &Path) {
+        let now = SystemTime::now();
+        self.modified
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), now);
+    }
+
+    fn add_directories(&self, path: &Path) {
+        let mut directories = self.directories.lock().unwrap();
+        for ancestor in path.ancestors() {
+            if !ancestor.as_os_str().is_empty() {
+                directories.insert(ancestor.to_path_buf());
+            }
+        }
+    }
+}
+
+impl FileSystem for InMemoryFileSystem {
+    fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.files
+            .lock()
+            .unwrap()
+            .get(&normalize(path))
+            .cloned()
+            .ok_or_else(|| anyhow!("{}: file not found", path.display()))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+        let path = normalize(path);
+        if let Some(parent) = path.parent()
+            && !parent.as_os_str().is_empty()
+            && !self.exists(parent)
+        {
+            return Err(anyhow!("{}: directory not found", parent.display()));
+        }
+        self.touch(&path);
+        self.files.lock().unwrap().insert(path, contents.to_vec());
+        Ok(())
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        let path = normalize(path);
+        self.files.lock().unwrap().contains_key(&path)
+            || self.directories.lock().unwrap().contains(&path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> Result<()> {
+        self.add_directories(&normalize(path));
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>> {
+        let path = normalize(path);
+        let files = self.files.lock().unwrap();
+        let directories = self.directories.lock().unwrap();
+        if !directories.contains(&path) {
+            return Err(anyhow!("{}: directory not found", path.display()));
+        }
+        let children = directories
+            .iter()
+            .map(|dir| (dir, true))
+            .chain(files.keys().map(|file| (file, false)));
+        let mut entries = Vec::new();
+        for (child, is_dir) in children {
+            if child.parent() == Some(path.as_path())
+                && let Some(name) = child.file_name()
+            {
+                entries.push(DirEntry {
+                    name: name.to_string_lossy().into_owned(),
+                    is_dir,
+                });
+            }
+        }
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    fn modified(&self, path: &Path) -> Result<SystemTime> {
+        self.modified
+            .lock()
+            .unwrap()
+            .get(&normalize(path))
+            .copied()
+            .ok_or_else(|| anyhow!("{}: file not found", path.display()))
+    }
+}
+
+/// Resolve `.` and `..` without touching the disk
+#[allow(dead_code)] // Public API for library users and tests
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match normalized.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    normalized.pop();
+                }
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => normalized.push(".."),
+            },
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Make `path` absolute against the current directory and normalize it, so
+/// searches up the directory tree don't stop at the current directory
+pub fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path)
+        .map(|absolute| normalize(&absolute))
+        .unwrap_or_else(|_| normalize(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_in_memory_file_system() -> Result<()> {
+        let fs = InMemoryFileSystem::new().with_file("/repo/src/app.proj", "<Project />");
+
+        assert!(fs.exists(Path::new("/repo/src/../src/./app.proj")));
+        assert!(fs.exists(Path::new("/repo")));
+        assert_eq!(
+            fs.read_to_string(Path::new("/repo/src/app.proj"))?,
+            "<Project />"
+        );
+
+        assert!(fs.write(Path::new("/repo/out/a.txt"), b"a").is_err());
+        fs.create_dir_all(Path::new("/repo/out"))?;
+        fs.copy(
+            Path::new("/repo/src/app.proj"),
+            Path::new("/repo/out/a.txt"),
+        )?;
+        assert_eq!(fs.read(Path::new("/repo/out/a.txt"))?, b"<Project />");
+
+        let names: Vec<_> = fs
+            .read_dir(Path::new("/repo"))?
+            .into_iter()
+            .map(|entry| (entry.name, entry.is_dir))
+            .collect();
+        assert_eq!(
+            names,
+            [("out".to_string(), true), ("src".to_string(), true)]
+        );
+
+        Ok(())
+    }
+}
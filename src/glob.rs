@@ -0,0 +1,289 @@
+//! Wildcard expansion of item specs.
+//!
+//! `*` and `?` match within a single path segment and `**` matches any number
+//! of directories. Enumeration goes through [`FileSystem`], lists only the
+//! directories a pattern can reach, and skips directories that an exclude
+//! pattern rules out as a whole (such as `bin/**`) instead of walking them.
+
+use anyhow::Result;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::file_system::FileSystem;
+
+/// A file found by [`expand`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GlobMatch {
+    /// The path as it would be written in the project, relative like the pattern
+    pub path: String,
+    /// The directories matched from the first wildcard on, with a trailing `/`
+    pub recursive_dir: String,
+}
+
+pub fn has_wildcard(spec: &str) -> bool {
+    spec.contains(['*', '?'])
+}
+
+/// Whether `path` is matched by `pattern`, which may be a plain path.
+/// Comparison ignores case and separator style.
+pub fn matches(pattern: &str, path: &str) -> bool {
+    match_segments(&segments(pattern), &segments(path))
+}
+
+/// Find the files matching `include` under `base`, minus those matching any of
+/// `excludes`, in a stable depth-first order sorted by name
+pub fn expand(
+    file_system: &dyn FileSystem,
+    base: &Path,
+    include: &str,
+    excludes: &[String],
+) -> Result<Vec<GlobMatch>> {
+    let mut pattern = segments(include);
+    // Segments before the first wildcard name a directory to start from
+    let fixed = pattern[..pattern.len() - 1]
+        .iter()
+        .take_while(|segment| !has_wildcard(segment))
+        .count();
+    let wildcards = pattern.split_off(fixed);
+    let mut wildcards: Vec<&str> = wildcards.iter().map(String::as_str).collect();
+    if wildcards.last() == Some(&"**") {
+        wildcards.push("*");
+    }
+
+    let mut walker = Walker {
+        file_system,
+        excludes: excludes.iter().map(|exclude| segments(exclude)).collect(),
+        fixed,
+        seen: HashSet::new(),
+        matches: Vec::new(),
+    };
+    let start = base.join(pattern.join("/"));
+    if !walker.is_pruned(&pattern) {
+        walker.walk(&start, &mut pattern, &wildcards)?;
+    }
+    Ok(walker.matches)
+}
+
+struct Walker<'a> {
+    file_system: &'a dyn FileSystem,
+    excludes: Vec<Vec<String>>,
+    fixed: usize,
+    seen: HashSet<String>,
+    matches: Vec<GlobMatch>,
+}
+
+impl Walker<'_> {
+    fn walk(&mut self, directory: &Path, path: &mut Vec<String>, pattern: &[&str]) -> Result<()> {
+        if !self.file_system.exists(directory) {
+            return Ok(());
+        }
+        let (segment, rest) = pattern.split_first().expect("pattern has a file name");
+
+        if rest.is_empty() {
+            for entry in self.file_system.read_dir(directory)? {
+                if !entry.is_dir && wildcard_match(segment, &entry.name) {
+                    path.push(entry.name);
+                    self.add_file(path);
+                    path.pop();
+                }
+            }
+        } else if *segment == "**" {
+            self.walk(directory, path, rest)?;
+            self.walk_subdirectories(directory, path, pattern, "*")?;
+        } else if has_wildcard(segment) {
+            self.walk_subdirectories(directory, path, rest, segment)?;
+        } else {
+            path.push(segment.to_string());
+            if !self.is_pruned(path) {
+                self.walk(&directory.join(segment), path, rest)?;
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    fn walk_subdirectories(
+        &mut self,
+        directory: &Path,
+        path: &mut Vec<String>,
+        pattern: &[&str],
+        name_pattern: &str,
+    ) -> Result<()> {
+        for entry in self.file_system.read_dir(directory)? {
+            if entry.is_dir && wildcard_match(name_pattern, &entry.name) {
+                let child = directory.join(&entry.name);
+                path.push(entry.name);
+                if !self.is_pruned(path) {
+                    self.walk(&child, path, pattern)?;
+                }
+                path.pop();
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: &[String]) {
+        if self
+            .excludes
+            .iter()
+            .any(|exclude| match_segments(exclude, path))
+        {
+            return;
+        }
+        let joined = path.join("/");
+        if self.seen.insert(joined.clone()) {
+            let directories = &path[self.fixed..path.len() - 1];
+            let recursive_dir = directories.iter().map(|dir| format!("{dir}/")).collect();
+            self.matches.push(GlobMatch {
+                path: joined,
+                recursive_dir,
+            });
+        }
+    }
+
+    /// Whether an exclude pattern ending in `**` covers the whole directory
+    fn is_pruned(&self, directory: &[String]) -> bool {
+        self.excludes.iter().any(|exclude| {
+            exclude.last().is_some_and(|last| last == "**")
+                && match_segments(&exclude[..exclude.len() - 1], directory)
+        })
+    }
+}
+
+/// Split a path into segments, with either separator and without `.` segments
+fn segments(path: &str) -> Vec<String> {
+    let path = path.replace('\\', "/");
+    let mut segments: Vec<String> = path
+        .split('/')
+        .enumerate()
+        // Keep a leading empty segment so absolute paths stay absolute
+        .filter(|&(index, segment)| segment != "." && (index == 0 || !segment.is_empty()))
+        .map(|(_, segment)| segment.to_string())
+        .collect();
+    if segments.is_empty() {
+        segments.push(String::new());
+    }
+    segments
+}
+
+fn match_segments(pattern: &[String], path: &[String]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((segment, rest)) if segment == "**" => {
+            match_segments(rest, path) || (!path.is_empty() && match_segments(pattern, &path[1..]))
+        }
+        Some((segment, rest)) => {
+            !path.is_empty()
+                && wildcard_match(segment, &path[0])
+                && match_segments(rest, &path[1..])
+        }
+    }
+}
+
+/// Match a single segment against `*` and `?`, ignoring ASCII case
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let name: Vec<char> = name.chars().map(|c| c.to_ascii_lowercase()).collect();
+    // matched[j]: whether the pattern so far matches the first j characters
+    let mut matched = vec![false; name.len() + 1];
+    matched[0] = true;
+    for p in pattern {
+        let previous = matched.clone();
+        matched[0] = p == '*' && previous[0];
+        for j in 1..=name.len() {
+            matched[j] = match p {
+                '*' => previous[j] || matched[j - 1],
+                '?' => previous[j - 1],
+                _ => previous[j - 1] && p == name[j - 1],
+            };
+        }
+    }
+    matched[name.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::{DirEntry, InMemoryFileSystem};
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+
+    /// Records the directories that were listed
+    struct ListingSpy {
+        inner: InMemoryFileSystem,
+        listed: Mutex<Vec<PathBuf>>,
+    }
+
+    impl FileSystem for ListingSpy {
+        fn read(&self, path: &Path) -> Result<Vec<u8>> {
+            self.inner.read(path)
+        }
+        fn write(&self, path: &Path, contents: &[u8]) -> Result<()> {
+            self.inner.write(path, contents)
+        }
+        fn exists(&self, path: &Path) -> bool {
+            self.inner.exists(path)
+        }
+        fn create_dir_all(&self, path: &Path) -> Result<()> {
+            self.inner.create_dir_all(path)
+        }
+        fn read_dir(&self, path: &Path) -> Result<Vec<DirEntry>> {
+            self.listed.lock().unwrap().push(path.to_path_buf());
+            self.inner.read_dir(path)
+        }
+        fn modified(&self, path: &Path) -> Result<std::time::SystemTime> {
+            self.inner.modified(path)
+        }
+    }
+
+    #[test]
+    fn test_expand_recursive_wildcards_with_excludes() -> Result<()> {
+        let fs = ListingSpy {
+            inner: InMemoryFileSystem::new()
+                .with_file("/repo/Program.cs", "")
+                .with_file("/repo/src/b.cs", "")
+                .with_file("/repo/src/a.cs", "")
+                .with_file("/repo/src/notes.txt", "")
+                .with_file("/repo/src/deep/x/c.cs", "")
+                .with_file("/repo/src/obj/gen.cs", "")
+                .with_file("/repo/bin/out.cs", ""),
+            listed: Mutex::new(Vec::new()),
+        };
+        let excludes = ["bin/**".to_string(), "**/obj/**".to_string()];
+
+        let found = expand(&fs, Path::new("/repo"), "**/*.cs", &excludes)?;
+        let found: Vec<(&str, &str)> = found
+            .iter()
+            .map(|m| (m.path.as_str(), m.recursive_dir.as_str()))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                ("Program.cs", ""),
+                ("src/a.cs", "src/"),
+                ("src/b.cs", "src/"),
+                ("src/deep/x/c.cs", "src/deep/x/"),
+            ]
+        );
+        let listed = fs.listed.lock().unwrap().clone();
+        assert!(!listed.contains(&PathBuf::from("/repo/bin")));
+        assert!(!listed.contains(&PathBuf::from("/repo/src/obj")));
+
+        let found = expand(&fs, Path::new("/repo"), "src/*/?/*.cs", &[])?;
+        assert_eq!(found[0].path, "src/deep/x/c.cs");
+        assert_eq!(found[0].recursive_dir, "deep/x/");
+        assert_eq!(expand(&fs, Path::new("/repo"), "missing/**", &[])?, []);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(matches("Generated/**", "generated/sub/a.cs"));
+        assert!(matches("**/*.cs", "a.cs"));
+        assert!(matches(r".\src\a.cs", "src/a.cs"));
+        assert!(matches("src/?.cs", "src/a.cs"));
+        assert!(!matches("src/*.cs", "src/sub/a.cs"));
+        assert!(!matches("*.cs", "a.csx"));
+    }
+}
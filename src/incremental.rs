@@ -0,0 +1,238 @@
+//! Up-to-date checks for targets with `Inputs` and `Outputs`.
+//!
+//! A target is skipped when every output is newer than every input. When the
+//! outputs are a transform of an item type the inputs list, such as
+//! `Inputs="@(Compile)" Outputs="@(Compile->'obj/%(Filename).o')"`, each item is
+//! paired with its own output and the target runs for the out-of-date items only.
+
+use anyhow::Result;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::expression::{ExpressionEvaluator, transform_item};
+use crate::file_system::FileSystem;
+use crate::object_model::{Item, ProjectModel, Target};
+
+/// How much of a target needs to run
+#[derive(Debug)]
+pub enum Analysis {
+    /// Every output is up to date; the reason is for the log
+    Skip(String),
+    /// Run the whole target, for the reasons given
+    Full(Vec<String>),
+    /// Run the target with `item_type` narrowed to the out-of-date `items`
+    Partial {
+        item_type: String,
+        items: Vec<Item>,
+        reasons: Vec<String>,
+    },
+}
+
+/// Compare the target's inputs and outputs, or `None` when the target does not
+/// declare both and always runs
+pub fn analyze(
+    target: &Target,
+    model: &ProjectModel,
+    file_system: &dyn FileSystem,
+) -> Result<Option<Analysis>> {
+    let (Some(inputs), Some(outputs)) = (&target.inputs, &target.outputs) else {
+        return Ok(None);
+    };
+    let checker = Checker {
+        file_system,
+        project_directory: model
+            .get_project_directory()
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+    let evaluator = ExpressionEvaluator::new(model);
+
+    if let Some((item_type, template)) = correlated_transform(inputs, outputs) {
+        let discrete = item_reference(&item_type).replace_all(inputs, "");
+        let discrete = split(&evaluator.evaluate(&discrete)?);
+        let template = evaluator.evaluate(&template)?;
+        let items = model.get_items(&item_type).cloned().unwrap_or_default();
+        if items.is_empty() && discrete.is_empty() {
+            return Ok(Some(no_inputs(target)));
+        }
+
+        let mut out_of_date = Vec::new();
+        let mut reasons = Vec::new();
+        for item in &items {
+            let output = transform_item(item, &template);
+            let inputs = std::iter::once(item.name.clone()).chain(discrete.iter().cloned());
+            if let Some(reason) = checker.out_of_date(inputs, std::iter::once(output))? {
+                out_of_date.push(item.clone());
+                reasons.push(reason);
+            }
+        }
+        return Ok(Some(if out_of_date.is_empty() {
+            up_to_date(target)
+        } else if out_of_date.len() == items.len() {
+            Analysis::Full(reasons)
+        } else {
+            Analysis::Partial {
+                item_type,
+                items: out_of_date,
+                reasons,
+            }
+        }));
+    }
+
+    let inputs = split(&evaluator.evaluate(inputs)?);
+    let outputs = split(&evaluator.evaluate(outputs)?);
+    if inputs.is_empty() {
+        return Ok(Some(no_inputs(target)));
+    }
+    if outputs.is_empty() {
+        return Ok(Some(Analysis::Skip(format!(
+            "Skipping target {} because it has no outputs.",
+            target.name
+        ))));
+    }
+    Ok(Some(match checker.out_of_date(inputs, outputs)? {
+        Some(reason) => Analysis::Full(vec![reason]),
+        None => up_to_date(target),
+    }))
+}
+
+fn up_to_date(target: &Target) -> Analysis {
+    Analysis::Skip(format!(
+        "Skipping target {} because all output files are up-to-date with respect to the input files.",
+        target.name
+    ))
+}
+
+fn no_inputs(target: &Target) -> Analysis {
+    Analysis::Skip(format!(
+        "Skipping target {} because it has no inputs.",
+        target.name
+    ))
+}
+
+/// The item type and template when `outputs` is exactly one transform of a type
+/// that `inputs` references
+fn correlated_transform(inputs: &str, outputs: &str) -> Option<(String, String)> {
+    let transform_regex =
+        Regex::new(r"^\s*@\(\s*([A-Za-z_][\w.-]*)\s*->\s*'([^']*)'\s*\)\s*$").unwrap();
+    let captures = transform_regex.captures(outputs)?;
+    let item_type = &captures[1];
+    item_reference(item_type)
+        .is_match(inputs)
+        .then(|| (item_type.to_string(), captures[2].to_string()))
+}
+
+fn item_reference(item_type: &str) -> Regex {
+    Regex::new(&format!(r"@\(\s*{}\s*\)", regex::escape(item_type))).unwrap()
+}
+
+fn split(list: &str) -> Vec<String> {
+    list.split(';')
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+struct Checker<'a> {
+    file_system: &'a dyn FileSystem,
+    project_directory: PathBuf,
+}
+
+impl Checker<'_> {
+    /// Why the outputs are out of date with respect to the inputs, if they are
+    fn out_of_date(
+        &self,
+        inputs: impl IntoIterator<Item = String>,
+        outputs: impl IntoIterator<Item = String>,
+    ) -> Result<Option<String>> {
+        let mut oldest_output: Option<(String, SystemTime)> = None;
+        for output in outputs {
+            let Some(modified) = self.modified(&output)? else {
+                return Ok(Some(format!("Output file {output} does not exist.")));
+            };
+            if oldest_output
+                .as_ref()
+                .is_none_or(|(_, oldest)| modified < *oldest)
+            {
+                oldest_output = Some((output, modified));
+            }
+        }
+        let Some((oldest_output, oldest)) = oldest_output else {
+            return Ok(None);
+        };
+
+        let mut newest_input: Option<(String, SystemTime)> = None;
+        for input in inputs {
+            let Some(modified) = self.modified(&input)? else {
+                return Ok(Some(format!("Input file {input} does not exist.")));
+            };
+            if newest_input
+                .as_ref()
+                .is_none_or(|(_, newest)| modified > *newest)
+            {
+                newest_input = Some((input, modified));
+            }
+        }
+        Ok(newest_input
+            .filter(|(_, newest)| *newest > oldest)
+            .map(|(input, _)| {
+                format!("Input file {input} is newer than output file {oldest_output}.")
+            }))
+    }
+
+    fn modified(&self, path: &str) -> Result<Option<SystemTime>> {
+        let path = self.project_directory.join(Path::new(path));
+        if !self.file_system.exists(&path) {
+            return Ok(None);
+        }
+        self.file_system.modified(&path).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::InMemoryFileSystem;
+    use crate::parser::ProjectParser;
+    use std::time::Duration;
+
+    #[test]
+    fn test_analysis_names_the_newer_input() -> Result<()> {
+        let fs = InMemoryFileSystem::new()
+            .with_file("/repo/a.cs", "")
+            .with_file("/repo/b.cs", "")
+            .with_file("/repo/out.dll", "");
+        let time = |seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds);
+        fs.set_modified("/repo/a.cs", time(30));
+        fs.set_modified("/repo/b.cs", time(10));
+        fs.set_modified("/repo/out.dll", time(20));
+
+        let model = ProjectParser::new().parse_str(
+            r#"<Project>
+  <Target Name="Link" Inputs="a.cs;b.cs" Outputs="out.dll" />
+  <Target Name="Pack" Inputs="b.cs" Outputs="out.dll;app.nupkg" />
+</Project>"#,
+            "/repo/app.proj".into(),
+        )?;
+        let analyze = |name| analyze(model.get_target(name).unwrap(), &model, &fs);
+
+        match analyze("Link")? {
+            Some(Analysis::Full(reasons)) => assert_eq!(
+                reasons,
+                ["Input file a.cs is newer than output file out.dll."]
+            ),
+            other => panic!("expected a full build, got {other:?}"),
+        }
+        match analyze("Pack")? {
+            Some(Analysis::Full(reasons)) => {
+                assert_eq!(reasons, ["Output file app.nupkg does not exist."])
+            }
+            other => panic!("expected a full build, got {other:?}"),
+        }
+        fs.set_modified("/repo/out.dll", time(40));
+        assert!(matches!(analyze("Link")?, Some(Analysis::Skip(_))));
+
+        Ok(())
+    }
+}
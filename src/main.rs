@@ -1,18 +1,30 @@
+mod build_result;
+mod construction;
+mod editing;
+mod encoding;
 mod evaluation;
 mod expression;
+mod file_system;
+mod glob;
+mod incremental;
 mod logger;
 mod object_model;
 mod parser;
+mod properties;
+mod sdk;
 mod tasks;
 mod tests;
+mod validation;
 
 use anyhow::Result;
-use clap::Parser;
-use log::info;
+use clap::{Parser, Subcommand};
+use log::{debug, info};
 use std::path::PathBuf;
 
+use crate::construction::ProjectRootElement;
 use crate::evaluation::ProjectEvaluator;
 use crate::logger::setup_logging;
+use crate::validation::ValidationMode;
 
 #[derive(Parser)]
 #[command(name = "msbuild-rs")]
@@ -22,24 +34,153 @@ struct Args {
     #[arg(short, long)]
     project: Option<PathBuf>,
 
-    /// Target to execute (default: "Build")
-    #[arg(short, long, default_value = "Build")]
-    target: String,
+    /// Targets to execute, separated by `;` (default: the project's DefaultTargets)
+    #[arg(short, long)]
+    target: Option<String>,
 
     /// Verbose logging
     #[arg(short, long)]
     verbose: bool,
 
+    /// How to treat unknown elements and attributes in project files
+    #[arg(long, value_enum, default_value_t = ValidationMode::Lenient)]
+    validation: ValidationMode,
+
+    /// Global properties as Name=Value, separated by `;` or `,`; also accepted
+    /// as `-p:` or `/p:` like MSBuild. May be repeated.
+    #[arg(long)]
+    property: Vec<String>,
+
+    /// Do not expose environment variables as properties, for hermetic builds
+    #[arg(long)]
+    no_environment: bool,
+
     /// Run demonstration with sample projects
     #[arg(long)]
     demo: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Normalize indentation and attribute quoting of project files, keeping comments
+    Fmt {
+        /// Project files to format in place
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Report files that would change instead of rewriting them
+        #[arg(long)]
+        check: bool,
+    },
+    /// Set a property, updating its unconditioned definition or adding one
+    SetProperty {
+        name: String,
+        value: String,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Remove every definition of a property
+    RemoveProperty {
+        name: String,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Add an item, e.g. `add-item PackageReference Serilog --metadata Version=3.1.1`
+    AddItem {
+        item_type: String,
+        include: String,
+        /// Item metadata as Name=Value, may be repeated
+        #[arg(long, value_parser = parse_key_value)]
+        metadata: Vec<(String, String)>,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Set metadata on existing items, e.g. to bump a PackageReference version
+    SetMetadata {
+        item_type: String,
+        include: String,
+        name: String,
+        value: String,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Remove items by type and Include
+    RemoveItem {
+        item_type: String,
+        include: String,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Add an empty target at the end of the project
+    AddTarget {
+        name: String,
+        /// Value for DependsOnTargets
+        #[arg(long)]
+        depends_on: Option<String>,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Remove a target
+    RemoveTarget {
+        name: String,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Add an Import after the existing ones
+    AddImport {
+        import: String,
+        /// Condition on the Import element
+        #[arg(long)]
+        condition: Option<String>,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+    /// Remove Import elements by their Project attribute
+    RemoveImport {
+        import: String,
+        #[arg(required = true)]
+        projects: Vec<PathBuf>,
+    },
+}
+
+fn parse_key_value(s: &str) -> Result<(String, String)> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("expected Name=Value, got `{s}`"))?;
+    Ok((key.trim().to_string(), value.to_string()))
+}
+
+/// Rewrite MSBuild-style `-p:Name=Value`, `/p:`, `-property:` and `--property:`
+/// switches as `--property Name=Value`, since `-p` alone names the project
+fn normalize_property_switches(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let mut normalized = Vec::new();
+    for arg in args {
+        let value = ["-p:", "/p:", "-property:", "--property:", "/property:"]
+            .iter()
+            .find_map(|prefix| arg.strip_prefix(prefix));
+        match value {
+            Some(value) => {
+                normalized.push("--property".to_string());
+                normalized.push(value.to_string());
+            }
+            None => normalized.push(arg),
+        }
+    }
+    normalized
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let args = Args::parse_from(normalize_property_switches(std::env::args()));
 
     setup_logging(args.verbose)?;
 
+    if let Some(command) = args.command {
+        return run_command(command);
+    }
+
     if args.demo {
         info!("Running demonstration mode");
         return run_sample_projects(&args);
@@ -51,16 +192,149 @@ fn main() -> Result<()> {
 
     info!("Starting MSBuild project execution");
     info!("Project: {}", project_path.display());
-    info!("Target: {}", args.target);
 
     let mut evaluator = ProjectEvaluator::new();
+    evaluator.set_validation_mode(args.validation);
+    if args.no_environment {
+        evaluator.set_environment([]);
+    }
+    let mut global_properties = Vec::new();
+    for switch in &args.property {
+        global_properties.extend(properties::parse_global_properties(switch)?);
+    }
+    evaluator.set_global_properties(global_properties);
     evaluator.load_project(&project_path)?;
-    evaluator.execute_target(&args.target)?;
+    let targets = match &args.target {
+        Some(targets) => targets
+            .split(';')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect(),
+        None => evaluator.default_targets()?,
+    };
+    info!("Targets: {}", targets.join(";"));
+    let result = evaluator.execute_targets(&targets);
+    for (name, target) in &result.targets {
+        debug!("Target {name} {}", target.outcome);
+        for item in &target.items {
+            debug!("  Returned {}", item.name);
+        }
+    }
+    result.into_result()?;
 
     info!("Build completed successfully");
     Ok(())
 }
 
+fn run_command(command: Command) -> Result<()> {
+    match command {
+        Command::Fmt { files, check } => {
+            let mut unformatted = Vec::new();
+            for file in files {
+                let original = ProjectRootElement::load(&file)?;
+                let mut formatted = original.clone();
+                formatted.format("  ");
+                if formatted == original {
+                    continue;
+                }
+                if check {
+                    info!("Would reformat {}", file.display());
+                    unformatted.push(file);
+                } else {
+                    formatted.save()?;
+                    info!("Formatted {}", file.display());
+                }
+            }
+            if !unformatted.is_empty() {
+                anyhow::bail!("{} file(s) are not formatted", unformatted.len());
+            }
+            Ok(())
+        }
+        Command::SetProperty {
+            name,
+            value,
+            projects,
+        } => edit_projects(&projects, |root| {
+            root.set_property(&name, &value);
+            Ok(())
+        }),
+        Command::RemoveProperty { name, projects } => edit_projects(&projects, |root| {
+            report_removed(root.remove_property(&name), &name);
+            Ok(())
+        }),
+        Command::AddItem {
+            item_type,
+            include,
+            metadata,
+            projects,
+        } => edit_projects(&projects, |root| {
+            root.add_item(&item_type, &include, &metadata);
+            Ok(())
+        }),
+        Command::SetMetadata {
+            item_type,
+            include,
+            name,
+            value,
+            projects,
+        } => edit_projects(&projects, |root| {
+            root.set_item_metadata(&item_type, &include, &name, &value)
+        }),
+        Command::RemoveItem {
+            item_type,
+            include,
+            projects,
+        } => edit_projects(&projects, |root| {
+            report_removed(root.remove_item(&item_type, &include), &include);
+            Ok(())
+        }),
+        Command::AddTarget {
+            name,
+            depends_on,
+            projects,
+        } => edit_projects(&projects, |root| {
+            root.add_target(&name, depends_on.as_deref())
+        }),
+        Command::RemoveTarget { name, projects } => edit_projects(&projects, |root| {
+            report_removed(root.remove_target(&name), &name);
+            Ok(())
+        }),
+        Command::AddImport {
+            import,
+            condition,
+            projects,
+        } => edit_projects(&projects, |root| {
+            root.add_import(&import, condition.as_deref());
+            Ok(())
+        }),
+        Command::RemoveImport { import, projects } => edit_projects(&projects, |root| {
+            report_removed(root.remove_import(&import), &import);
+            Ok(())
+        }),
+    }
+}
+
+/// Load each project, apply an edit and save it back
+fn edit_projects(
+    projects: &[PathBuf],
+    edit: impl Fn(&mut ProjectRootElement) -> Result<()>,
+) -> Result<()> {
+    for project in projects {
+        let mut root = ProjectRootElement::load(project)?;
+        edit(&mut root).map_err(|e| anyhow::anyhow!("{}: {e}", project.display()))?;
+        root.save()?;
+        info!("Updated {}", project.display());
+    }
+    Ok(())
+}
+
+fn report_removed(count: usize, what: &str) {
+    if count == 0 {
+        log::warn!("Nothing named {what} to remove");
+    }
+}
+
 fn run_sample_projects(_args: &Args) -> Result<()> {
     info!("=== MSBuild-RS Demonstration ===");
 
@@ -70,8 +344,8 @@ fn run_sample_projects(_args: &Args) -> Result<()> {
     if simple_project.exists() {
         let mut evaluator = ProjectEvaluator::new();
         match evaluator.load_project(&simple_project) {
-            Ok(()) => match evaluator.execute_target("Build") {
-                Ok(()) => info!("✓ Simple project executed successfully"),
+            Ok(()) => match evaluator.execute_target("Build").into_result() {
+                Ok(_) => info!("✓ Simple project executed successfully"),
                 Err(e) => info!("✗ Failed to execute Build target: {e}"),
             },
             Err(e) => info!("✗ Could not load simple.proj: {e}"),
@@ -87,12 +361,12 @@ fn run_sample_projects(_args: &Args) -> Result<()> {
         let mut evaluator = ProjectEvaluator::new();
         match evaluator.load_project(&conditional_project) {
             Ok(()) => {
-                match evaluator.execute_target("Test") {
-                    Ok(()) => info!("✓ Conditional Test target executed successfully"),
+                match evaluator.execute_target("Test").into_result() {
+                    Ok(_) => info!("✓ Conditional Test target executed successfully"),
                     Err(e) => info!("✗ Failed to execute Test target: {e}"),
                 }
-                match evaluator.execute_target("Build") {
-                    Ok(()) => info!("✓ Conditional Build target executed successfully"),
+                match evaluator.execute_target("Build").into_result() {
+                    Ok(_) => info!("✓ Conditional Build target executed successfully"),
                     Err(e) => info!("✗ Failed to execute Build target: {e}"),
                 }
             }
@@ -111,8 +385,8 @@ fn run_sample_projects(_args: &Args) -> Result<()> {
     if import_project.exists() {
         let mut evaluator = ProjectEvaluator::new();
         match evaluator.load_project(&import_project) {
-            Ok(()) => match evaluator.execute_target("Build") {
-                Ok(()) => info!("✓ Import project executed successfully"),
+            Ok(()) => match evaluator.execute_target("Build").into_result() {
+                Ok(_) => info!("✓ Import project executed successfully"),
                 Err(e) => info!("✗ Failed to execute Build target: {e}"),
             },
             Err(e) => info!("✗ Could not load with_imports.proj: {e}"),
@@ -1,21 +1,115 @@
 use indexmap::IndexMap;
 use std::collections::HashMap;
+use std::fmt;
 use std::path::PathBuf;
 
+use crate::construction::XmlElement;
+use crate::properties;
+use crate::sdk::SdkReference;
+
+/// Where an element was read from, reported as `file(line,col)` like MSBuild and
+/// the editors that parse its output.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceLocation {
+    pub file: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourceLocation {
+    pub fn new(file: PathBuf, line: usize, column: usize) -> Self {
+        Self { file, line, column }
+    }
+
+    /// Build an error that points at this location.
+    pub fn error(&self, message: impl fmt::Display) -> anyhow::Error {
+        anyhow::anyhow!("{self}: error: {message}")
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}({},{})", self.file.display(), self.line, self.column)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Item {
     pub item_type: String,
     pub name: String,
-    #[allow(dead_code)] // Metadata support planned for future implementation
     pub metadata: HashMap<String, String>,
+    #[allow(dead_code)] // Kept for diagnostics about individual items
+    pub location: SourceLocation,
+}
+
+impl Item {
+    /// The value of custom metadata, or of well-known metadata derived from the
+    /// item spec such as `Filename` and `Extension`
+    pub fn metadata_value(&self, name: &str) -> Option<String> {
+        if let Some(value) = self.metadata.get(name) {
+            return Some(value.clone());
+        }
+        let spec = self.name.replace('\\', "/");
+        let (directory, file) = spec.rsplit_once('/').unwrap_or(("", &spec));
+        let (filename, extension) = match file.rfind('.') {
+            Some(dot) if dot > 0 => (&file[..dot], &file[dot..]),
+            _ => (file, ""),
+        };
+        let value = match name {
+            "Identity" => self.name.clone(),
+            "Filename" => filename.to_string(),
+            "Extension" => extension.to_string(),
+            "RelativeDir" if directory.is_empty() => String::new(),
+            "RelativeDir" => format!("{directory}/"),
+            "RecursiveDir" => String::new(),
+            _ => return None,
+        };
+        Some(value)
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Target {
     pub name: String,
     pub depends_on: Vec<String>,
+    pub before_targets: Vec<String>,
+    pub after_targets: Vec<String>,
+    /// Files the target reads and writes, for skipping it when up to date
+    pub inputs: Option<String>,
+    pub outputs: Option<String>,
+    /// Items handed to callers; `Outputs` is returned when this is absent
+    pub returns: Option<String>,
+    pub condition: Option<String>,
+    pub children: Vec<TargetChild>,
+    pub on_error: Vec<OnError>,
+    pub location: SourceLocation,
+}
+
+/// An `<OnError>` element: targets to run when a task of the target fails
+#[derive(Debug, Clone)]
+pub struct OnError {
+    pub execute_targets: String,
     pub condition: Option<String>,
-    pub tasks: Vec<Task>,
+    pub location: SourceLocation,
+}
+
+impl Target {
+    #[allow(dead_code)] // Public API method for library users
+    pub fn tasks(&self) -> impl Iterator<Item = &Task> {
+        self.children.iter().filter_map(|child| match child {
+            TargetChild::Task(task) => Some(task),
+            _ => None,
+        })
+    }
+}
+
+/// What a target runs, in document order
+#[derive(Debug, Clone)]
+pub enum TargetChild {
+    Task(Task),
+    /// Evaluated when execution reaches it, so later tasks see the changes
+    PropertyGroup(XmlElement),
+    ItemGroup(XmlElement),
 }
 
 #[derive(Debug, Clone)]
@@ -23,22 +117,53 @@ pub struct Task {
     pub name: String,
     pub attributes: HashMap<String, String>,
     pub condition: Option<String>,
+    pub outputs: Vec<TaskOutput>,
+    pub location: SourceLocation,
+}
+
+/// An `<Output>` element: where to put one of the task's output parameters
+#[derive(Debug, Clone)]
+pub struct TaskOutput {
+    pub task_parameter: String,
+    pub property_name: Option<String>,
+    pub item_name: Option<String>,
+    pub condition: Option<String>,
+    pub location: SourceLocation,
 }
 
+/// An `<Import>` whose condition held, kept for inspecting what a project imported
+#[allow(dead_code)] // Public API for library users
 #[derive(Debug, Clone)]
 pub struct Import {
     pub project: String,
+    /// The SDK whose directory `project` is relative to, from `Sdk="..."`
+    pub sdk: Option<SdkReference>,
     pub condition: Option<String>,
+    pub location: SourceLocation,
 }
 
 #[derive(Debug, Default)]
 pub struct ProjectModel {
     pub properties: IndexMap<String, String>,
+    /// Reserved properties such as `MSBuildProjectDirectory`, which files cannot set
+    pub reserved_properties: IndexMap<String, String>,
+    /// Environment variables, which any property the project defines overrides
+    pub environment_properties: IndexMap<String, String>,
+    /// Properties given to the whole build, such as with `-p:Name=Value`
+    pub global_properties: IndexMap<String, String>,
+    pub property_locations: HashMap<String, SourceLocation>, // property name -> last definition
     pub items: IndexMap<String, Vec<Item>>,
+    pub item_definitions: IndexMap<String, HashMap<String, String>>, // item type -> default metadata
     pub targets: IndexMap<String, Target>,
     pub imports: Vec<Import>,
     pub using_tasks: HashMap<String, String>, // task name -> assembly
-    pub project_file_path: Option<PathBuf>,   // Path to the project file
+    /// `DefaultTargets` of the first file that declares it, unexpanded
+    pub default_targets: Option<String>,
+    /// `InitialTargets` of every file, unexpanded, in the order they were read
+    pub initial_targets: Vec<String>,
+    /// Global properties the project may override, from `TreatAsLocalProperty`
+    pub treat_as_local_properties: Vec<String>,
+    pub project_file_path: Option<PathBuf>, // Path to the project file
 }
 
 impl ProjectModel {
@@ -46,15 +171,70 @@ impl ProjectModel {
         Self::default()
     }
 
+    #[allow(dead_code)] // Public API method for library users
     pub fn set_property(&mut self, name: String, value: String) {
+        self.property_locations.remove(&name);
+        self.properties.insert(name, value);
+    }
+
+    /// Set a property defined by a project file. Reserved properties cannot be
+    /// set, and global properties keep their value unless the project treats
+    /// them as local.
+    pub fn set_property_at(
+        &mut self,
+        name: String,
+        value: String,
+        location: SourceLocation,
+    ) -> anyhow::Result<()> {
+        if properties::is_reserved(&name) {
+            return Err(location.error(format!(
+                "The reserved property \"{name}\" cannot be modified"
+            )));
+        }
+        if self.global_properties.contains_key(&name)
+            && !self.treat_as_local_properties.contains(&name)
+        {
+            log::debug!("{location}: Keeping the value of global property {name}");
+            return Ok(());
+        }
+        self.property_locations.insert(name.clone(), location);
         self.properties.insert(name, value);
+        Ok(())
     }
 
+    /// Look up a property. Global properties win over project values unless the
+    /// project treats them as local.
     pub fn get_property(&self, name: &str) -> Option<&String> {
-        self.properties.get(name)
+        let global = self
+            .global_properties
+            .get(name)
+            .filter(|_| !self.treat_as_local_properties.iter().any(|n| n == name));
+        self.reserved_properties
+            .get(name)
+            .or(global)
+            .or_else(|| self.properties.get(name))
+            .or_else(|| self.global_properties.get(name))
+            .or_else(|| self.environment_properties.get(name))
+    }
+
+    pub fn set_reserved_property(&mut self, name: &str, value: String) {
+        self.reserved_properties.insert(name.to_string(), value);
     }
 
-    pub fn add_item(&mut self, item: Item) {
+    /// Where the current value of a property was defined, if it came from a file
+    #[allow(dead_code)] // Public API method for library users
+    pub fn get_property_location(&self, name: &str) -> Option<&SourceLocation> {
+        self.property_locations.get(name)
+    }
+
+    pub fn add_item(&mut self, mut item: Item) {
+        if let Some(defaults) = self.item_definitions.get(&item.item_type) {
+            for (name, value) in defaults {
+                item.metadata
+                    .entry(name.clone())
+                    .or_insert_with(|| value.clone());
+            }
+        }
         self.items
             .entry(item.item_type.clone())
             .or_default()
@@ -65,6 +245,26 @@ impl ProjectModel {
         self.items.get(item_type)
     }
 
+    /// Remove the items of a type for which `predicate` holds
+    pub fn remove_items(&mut self, item_type: &str, predicate: impl Fn(&Item) -> bool) {
+        if let Some(items) = self.items.get_mut(item_type) {
+            items.retain(|item| !predicate(item));
+        }
+    }
+
+    /// Give items of a type default metadata, applied as items are added.
+    /// Definitions are evaluated in a pass before items, so every item sees them.
+    pub fn add_item_definition_metadata(&mut self, item_type: &str, name: String, value: String) {
+        self.item_definitions
+            .entry(item_type.to_string())
+            .or_default()
+            .insert(name, value);
+    }
+
+    pub fn get_item_definition(&self, item_type: &str) -> Option<&HashMap<String, String>> {
+        self.item_definitions.get(item_type)
+    }
+
     pub fn add_target(&mut self, target: Target) {
         self.targets.insert(target.name.clone(), target);
     }
@@ -81,6 +281,7 @@ impl ProjectModel {
         self.using_tasks.insert(task_name, assembly);
     }
 
+    #[allow(dead_code)] // Public API method for library users
     pub fn get_all_item_names(&self, item_type: &str) -> String {
         if let Some(items) = self.get_items(item_type) {
             items
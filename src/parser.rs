@@ -1,267 +1,986 @@
-use anyhow::{Result, anyhow};
-use quick_xml::Reader;
-use quick_xml::events::{BytesStart, Event};
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
-use std::path::Path;
+use anyhow::Result;
+use indexmap::IndexMap;
+use log::{info, warn};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
+use crate::construction::{MSBUILD_NAMESPACE, ProjectRootElement, XmlElement};
 use crate::expression::ExpressionEvaluator;
-use crate::object_model::{Import, Item, ProjectModel, Target, Task};
+use crate::file_system::{self, FileSystem, PhysicalFileSystem};
+use crate::glob;
+use crate::object_model::{
+    Import, Item, OnError, ProjectModel, SourceLocation, Target, TargetChild, Task, TaskOutput,
+};
+use crate::properties;
+use crate::sdk::{
+    self, LocalSdkResolver, SDKS_PATH_VARIABLE, SdkReference, SdkResolution, SdkResolver,
+};
+use crate::validation::{self, ITEM_ATTRIBUTES, ValidationMode};
 
 pub struct ProjectParser {
     model: ProjectModel,
+    file_system: Arc<dyn FileSystem>,
+    validation_mode: ValidationMode,
+    /// Finds SDKs; by default a [`LocalSdkResolver`] for the environment
+    sdk_resolver: Option<Arc<dyn SdkResolver>>,
+    /// The files being evaluated, outermost first, for relative imports and cycles
+    file_stack: Vec<PathBuf>,
+    /// Every file evaluated so far, so each is imported only once
+    imported: HashSet<PathBuf>,
+    environment: IndexMap<String, String>,
+    global_properties: IndexMap<String, String>,
+    /// Elements that later passes evaluate, gathered while properties and
+    /// imports are evaluated
+    deferred: DeferredElements,
+}
+
+/// Elements of the project and its imports in document order. Item definitions
+/// and items keep the file they came from for its `MSBuildThisFile*` properties.
+#[derive(Default)]
+struct DeferredElements {
+    item_definition_groups: Vec<(XmlElement, PathBuf)>,
+    item_groups: Vec<(XmlElement, PathBuf)>,
+    using_tasks: Vec<XmlElement>,
+    targets: Vec<XmlElement>,
 }
 
 impl ProjectParser {
+    #[allow(dead_code)] // Public API method for library users
     pub fn new() -> Self {
+        Self::with_file_system(PhysicalFileSystem::shared())
+    }
+
+    pub fn with_file_system(file_system: Arc<dyn FileSystem>) -> Self {
         Self {
             model: ProjectModel::new(),
+            file_system,
+            validation_mode: ValidationMode::default(),
+            sdk_resolver: None,
+            file_stack: Vec::new(),
+            imported: HashSet::new(),
+            environment: IndexMap::new(),
+            global_properties: IndexMap::new(),
+            deferred: DeferredElements::default(),
         }
     }
 
+    /// How schema violations in imported files are treated
+    pub fn with_validation_mode(mut self, mode: ValidationMode) -> Self {
+        self.validation_mode = mode;
+        self
+    }
+
+    /// Environment variables to expose as properties, from
+    /// [`properties::environment_properties`]
+    pub fn with_environment(mut self, environment: IndexMap<String, String>) -> Self {
+        self.environment = environment;
+        self
+    }
+
+    /// Properties that hold for the whole evaluation and that project files
+    /// cannot change, except those named by `TreatAsLocalProperty`
+    pub fn with_global_properties(mut self, properties: IndexMap<String, String>) -> Self {
+        self.global_properties = properties;
+        self
+    }
+
+    pub fn with_sdk_resolver(mut self, resolver: Arc<dyn SdkResolver>) -> Self {
+        self.sdk_resolver = Some(resolver);
+        self
+    }
+
+    #[allow(dead_code)] // Public API method for library users
     pub fn parse_file<P: AsRef<Path>>(&mut self, path: P) -> Result<ProjectModel> {
-        let file = File::open(&path)?;
-        let buf_reader = BufReader::new(file);
-        let mut reader = Reader::from_reader(buf_reader);
-        reader.config_mut().trim_text(true);
-
-        let mut buf = Vec::new();
-        let mut current_target: Option<Target> = None;
-        let mut current_task: Option<Task> = None;
-        let mut in_property_group = false;
-        let mut in_item_group = false;
-        let mut current_property_name: Option<String> = None;
-        let mut current_item_type: Option<String> = None;
-        let mut current_item_include: Option<String> = None;
-        let mut current_item_metadata: HashMap<String, String> = HashMap::new();
-
-        // First pass: collect all properties and static elements
-        loop {
-            match reader.read_event_into(&mut buf) {
-                Ok(Event::Start(ref e)) => {
-                    let name_bytes = e.name();
-                    let name = std::str::from_utf8(name_bytes.as_ref())?;
-                    let attributes = self.parse_attributes(e)?;
-
-                    match name {
-                        "Project" => {
-                            // Root element, continue parsing
-                        }
-                        "PropertyGroup" => {
-                            if self.should_process_conditional(&attributes)? {
-                                in_property_group = true;
-                            }
-                        }
-                        "ItemGroup" => {
-                            if self.should_process_conditional(&attributes)? {
-                                in_item_group = true;
-                            }
-                        }
-                        "Target" => {
-                            // Always load targets regardless of their conditions
-                            // Conditions will be evaluated during execution phase
-                            let target_name = attributes
-                                .get("Name")
-                                .ok_or_else(|| anyhow!("Target missing Name attribute"))?
-                                .clone();
-
-                            let depends_on = attributes
-                                .get("DependsOnTargets")
-                                .map(|deps| deps.split(';').map(|s| s.trim().to_string()).collect())
-                                .unwrap_or_default();
-
-                            current_target = Some(Target {
-                                name: target_name,
-                                depends_on,
-                                condition: attributes.get("Condition").cloned(),
-                                tasks: Vec::new(),
-                            });
-                        }
-                        "Import" => {
-                            if self.should_process_conditional(&attributes)? {
-                                if let Some(project) = attributes.get("Project") {
-                                    self.model.add_import(Import {
-                                        project: project.clone(),
-                                        condition: attributes.get("Condition").cloned(),
-                                    });
-                                }
-                            }
-                        }
-                        "UsingTask" => {
-                            if let (Some(task_name), Some(assembly)) =
-                                (attributes.get("TaskName"), attributes.get("AssemblyName"))
-                            {
-                                self.model
-                                    .add_using_task(task_name.clone(), assembly.clone());
-                            }
-                        }
-                        task_name if current_target.is_some() => {
-                            // This is a task within a target
-                            current_task = Some(Task {
-                                name: task_name.to_string(),
-                                attributes: attributes.clone(),
-                                condition: attributes.get("Condition").cloned(),
-                            });
-                        }
-                        property_name if in_property_group => {
-                            // Only set property name if there's no condition or condition is true
-                            if self.should_process_conditional(&attributes)? {
-                                current_property_name = Some(property_name.to_string());
-                            }
-                        }
-                        item_type if in_item_group => {
-                            current_item_type = Some(item_type.to_string());
-                            current_item_metadata.clear();
-                            current_item_include = attributes.get("Include").cloned();
-                        }
-                        _ => {
-                            // Unknown element, skip
-                        }
-                    }
+        let root = ProjectRootElement::load_from(self.file_system.as_ref(), path.as_ref())?;
+        self.parse_root(&root)
+    }
+
+    /// Parse project XML held in memory; `path` is used for locations
+    #[allow(dead_code)] // Public API method for library users
+    pub fn parse_str(&mut self, content: &str, path: PathBuf) -> Result<ProjectModel> {
+        let root = ProjectRootElement::parse(content, path)?;
+        self.parse_root(&root)
+    }
+
+    #[allow(dead_code)] // Public API method for library users
+    pub fn parse_reader(&mut self, reader: impl Read, path: PathBuf) -> Result<ProjectModel> {
+        let root = ProjectRootElement::from_reader(reader, path)?;
+        self.parse_root(&root)
+    }
+
+    /// Evaluate an already loaded construction tree into a project model in
+    /// MSBuild's passes: properties and imports, where each `<Import>` appears,
+    /// then item definitions, then items, then targets
+    pub fn parse_root(&mut self, root: &ProjectRootElement) -> Result<ProjectModel> {
+        // Wildcards in items are relative to the project file, even inside
+        // imports. An absolute path lets wildcards and searches for files above
+        // the project work when it is given relative to the current directory.
+        let project_path = file_system::absolute(&root.path);
+        self.model.set_project_file_path(project_path.clone());
+        self.file_stack.clear();
+        self.imported.clear();
+        self.deferred = DeferredElements::default();
+        self.model.environment_properties = self.environment.clone();
+        self.model.global_properties = self.global_properties.clone();
+        let reserved = properties::project_properties(&root.path)
+            .into_iter()
+            .chain(properties::tool_properties());
+        for (name, value) in reserved {
+            self.model.set_reserved_property(name, value);
+        }
+
+        self.evaluate_file(root)?;
+
+        let deferred = std::mem::take(&mut self.deferred);
+        for (group, file) in &deferred.item_definition_groups {
+            self.set_this_file_properties(file);
+            self.process_item_definition_group(group)?;
+        }
+        for (group, file) in &deferred.item_groups {
+            self.set_this_file_properties(file);
+            self.process_item_group(group)?;
+        }
+        self.set_this_file_properties(&project_path);
+        for element in &deferred.using_tasks {
+            self.process_using_task(element);
+        }
+        for element in &deferred.targets {
+            let target = self.create_target(element)?;
+            self.model.add_target(target);
+        }
+        Ok(std::mem::take(&mut self.model))
+    }
+
+    fn evaluate_file(&mut self, root: &ProjectRootElement) -> Result<()> {
+        let path = file_system::absolute(&root.path);
+        self.imported.insert(path.clone());
+        self.set_this_file_properties(&path);
+        self.file_stack.push(path);
+        let result = self.evaluate_project_element(root.project());
+        self.file_stack.pop();
+        if let Some(file) = self.file_stack.last().cloned() {
+            self.set_this_file_properties(&file);
+        }
+        result
+    }
+
+    /// Point the `MSBuildThisFile*` properties at `file`
+    fn set_this_file_properties(&mut self, file: &Path) {
+        for (name, value) in properties::this_file_properties(file) {
+            self.model.set_reserved_property(name, value);
+        }
+    }
+
+    /// The file being evaluated
+    fn current_file(&self) -> PathBuf {
+        self.file_stack.last().cloned().unwrap_or_default()
+    }
+
+    fn evaluate_project_element(&mut self, project: &XmlElement) -> Result<()> {
+        if project.msbuild_name() != Some("Project") {
+            return Err(project.location.error(format!(
+                "The root element must be <Project>, optionally in the {MSBUILD_NAMESPACE} namespace"
+            )));
+        }
+
+        // The first DefaultTargets wins; InitialTargets accumulate
+        if self.model.default_targets.is_none() {
+            self.model.default_targets = project.attribute("DefaultTargets");
+        }
+        self.model
+            .initial_targets
+            .extend(project.attribute("InitialTargets"));
+        if let Some(names) = project.attribute("TreatAsLocalProperty") {
+            for name in names.split(';').map(str::trim) {
+                if !name.is_empty()
+                    && !self
+                        .model
+                        .treat_as_local_properties
+                        .iter()
+                        .any(|n| n == name)
+                {
+                    self.model.treat_as_local_properties.push(name.to_string());
                 }
-                Ok(Event::Empty(ref e)) => {
-                    // Handle self-closing tags like <Compile Include="file.cs" />
-                    let name_bytes = e.name();
-                    let name = std::str::from_utf8(name_bytes.as_ref())?;
-                    let attributes = self.parse_attributes(e)?;
-
-                    if in_item_group {
-                        // This is an item definition
-                        if let Some(include) = attributes.get("Include") {
-                            self.process_item(name.to_string(), include.clone(), HashMap::new())?;
-                        }
-                    } else if current_target.is_some() {
-                        // This is a task within a target
-                        let task = Task {
-                            name: name.to_string(),
-                            attributes: attributes.clone(),
-                            condition: attributes.get("Condition").cloned(),
-                        };
-
-                        if let Some(ref mut target) = current_target {
-                            target.tasks.push(task);
-                        }
-                    }
+            }
+        }
+
+        // Project SDKs import Sdk.props before the body and Sdk.targets after it
+        let mut sdk_directories = Vec::new();
+        for sdk in sdk::project_sdks(project)? {
+            sdk_directories.push(self.resolve_sdk(&sdk, &project.location)?);
+        }
+        for directory in &sdk_directories {
+            self.import_sdk_file(&directory.join("Sdk.props"), &project.location)?;
+        }
+        // Shared settings of the directory tree wrap the project itself, not its imports.
+        // As with the .NET SDK, Directory.Build.props follows Sdk.props, so an SDK can
+        // set defaults for it or turn it off.
+        let is_project_file = self.file_stack.len() == 1;
+        if is_project_file {
+            self.import_directory_build_file(
+                "Directory.Build.props",
+                "ImportDirectoryBuildProps",
+                "DirectoryBuildPropsPath",
+                &project.location,
+            )?;
+        }
+
+        for (name, element) in project.msbuild_children() {
+            match name {
+                "PropertyGroup" => self.process_property_group(element)?,
+                "ItemGroup" => self
+                    .deferred
+                    .item_groups
+                    .push((element.clone(), self.current_file())),
+                "ItemDefinitionGroup" => self
+                    .deferred
+                    .item_definition_groups
+                    .push((element.clone(), self.current_file())),
+                // Target conditions are evaluated when the target runs
+                "Target" => self.deferred.targets.push(element.clone()),
+                "Import" => self.process_import(element)?,
+                "ImportGroup" => self.process_import_group(element)?,
+                "Choose" => self.process_choose(element)?,
+                "UsingTask" => self.deferred.using_tasks.push(element.clone()),
+                _ => {
+                    // Unknown element, skip
                 }
-                Ok(Event::End(ref e)) => {
-                    let name_bytes = e.name();
-                    let name = std::str::from_utf8(name_bytes.as_ref())?;
+            }
+        }
 
-                    match name {
-                        "PropertyGroup" => {
-                            in_property_group = false;
-                        }
-                        "ItemGroup" => {
-                            in_item_group = false;
-                        }
-                        "Target" => {
-                            if let Some(target) = current_target.take() {
-                                self.model.add_target(target);
-                            }
-                        }
-                        _task_name if current_task.is_some() => {
-                            if let Some(task) = current_task.take() {
-                                if let Some(ref mut target) = current_target {
-                                    target.tasks.push(task);
-                                }
-                            }
-                        }
-                        property_name
-                            if in_property_group
-                                && current_property_name.as_ref()
-                                    == Some(&property_name.to_string()) =>
-                        {
-                            current_property_name = None;
-                        }
-                        item_type
-                            if in_item_group
-                                && current_item_type.as_ref() == Some(&item_type.to_string()) =>
-                        {
-                            // Process item after we have all properties
-                            if let (Some(item_type), Some(include)) =
-                                (&current_item_type, &current_item_include)
-                            {
-                                self.process_item(
-                                    item_type.clone(),
-                                    include.clone(),
-                                    current_item_metadata.clone(),
-                                )?;
-                            }
-                            current_item_type = None;
-                            current_item_include = None;
-                        }
-                        _ => {}
-                    }
+        if is_project_file {
+            self.import_directory_build_file(
+                "Directory.Build.targets",
+                "ImportDirectoryBuildTargets",
+                "DirectoryBuildTargetsPath",
+                &project.location,
+            )?;
+        }
+        for directory in &sdk_directories {
+            self.import_sdk_file(&directory.join("Sdk.targets"), &project.location)?;
+        }
+        Ok(())
+    }
+
+    /// Import the nearest `file_name` at or above the project directory, unless
+    /// the `switch` property is false. The `path_property` property names the
+    /// file explicitly and is set to the file found otherwise.
+    fn import_directory_build_file(
+        &mut self,
+        file_name: &str,
+        switch: &str,
+        path_property: &str,
+        location: &SourceLocation,
+    ) -> Result<()> {
+        let evaluator = ExpressionEvaluator::new(&self.model);
+        if let Some(enabled) = self.model.get_property(switch)
+            && evaluator
+                .evaluate(enabled)?
+                .trim()
+                .eq_ignore_ascii_case("false")
+        {
+            info!("Not importing {file_name} because {switch} is false");
+            return Ok(());
+        }
+
+        // A path given by the project or on the command line is used as is
+        let project_directory = self.model.get_project_directory().unwrap_or_default();
+        let given = match self.model.get_property(path_property) {
+            Some(path) => evaluator.evaluate(path)?.trim().to_string(),
+            None => String::new(),
+        };
+        let path = match given.is_empty() {
+            false => project_directory.join(given),
+            true => {
+                let found = project_directory
+                    .ancestors()
+                    .map(|directory| directory.join(file_name))
+                    .find(|path| self.file_system.exists(path));
+                let Some(found) = found else {
+                    return Ok(());
+                };
+                self.model.set_property_at(
+                    path_property.to_string(),
+                    found.display().to_string(),
+                    location.clone(),
+                )?;
+                found
+            }
+        };
+        if self.file_system.exists(&path) {
+            self.import_file(&path, location)?;
+        }
+        Ok(())
+    }
+
+    /// Run a `PropertyGroup` or `ItemGroup` from inside a target against the
+    /// model of the running build
+    pub fn apply_target_group(
+        &mut self,
+        model: &mut ProjectModel,
+        group: &XmlElement,
+    ) -> Result<()> {
+        std::mem::swap(&mut self.model, model);
+        let result = match group.msbuild_name() {
+            Some("PropertyGroup") => self.process_target_property_group(group),
+            Some("ItemGroup") => self.process_target_item_group(group),
+            _ => Ok(()),
+        };
+        std::mem::swap(&mut self.model, model);
+        result
+    }
+
+    /// As at evaluation time, values are expanded when set, but item references
+    /// see the items of the running build
+    fn process_target_property_group(&mut self, group: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(group)? {
+            return Ok(());
+        }
+        for (name, property) in group.msbuild_children() {
+            if !self.should_process_conditional(property)? {
+                continue;
+            }
+            let evaluator = ExpressionEvaluator::new(&self.model);
+            let value = evaluator.evaluate(property.text().trim())?;
+            self.model
+                .set_property_at(name.to_string(), value, property.location.clone())?;
+        }
+        Ok(())
+    }
+
+    fn process_target_item_group(&mut self, group: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(group)? {
+            return Ok(());
+        }
+        for (name, item) in group.msbuild_children() {
+            if let Some(include) = item.attribute("Include") {
+                if !self.should_process_conditional(item)? {
+                    continue;
                 }
-                Ok(Event::Text(e)) => {
-                    let text = e.decode()?.trim().to_string();
-                    if !text.is_empty() {
-                        if let Some(ref prop_name) = current_property_name {
-                            // Store the raw property value, don't evaluate yet
-                            self.model.set_property(prop_name.clone(), text);
+                self.include_target_items(name, item, &include)?;
+            } else if let Some(remove) = item.attribute("Remove") {
+                if !self.should_process_conditional(item)? {
+                    continue;
+                }
+                self.remove_target_items(name, item, &remove)?;
+            } else {
+                // Without Include or Remove, the element changes the metadata of
+                // every item of the type for which its condition holds
+                let Some(mut items) = self.model.get_items(name).cloned() else {
+                    continue;
+                };
+                for existing in &mut items {
+                    if let Some(condition) = item.attribute("Condition") {
+                        let evaluator = ExpressionEvaluator::new(&self.model).with_item(existing);
+                        if !evaluator.evaluate_condition(&condition)? {
+                            continue;
                         }
                     }
+                    let metadata = self.item_metadata(item, existing)?;
+                    existing.metadata.extend(metadata);
                 }
-                Ok(Event::Eof) => break,
-                Err(e) => return Err(anyhow!("Error parsing XML: {}", e)),
-                _ => {}
+                self.model.items.insert(name.to_string(), items);
             }
-            buf.clear();
         }
+        Ok(())
+    }
 
-        Ok(std::mem::take(&mut self.model))
+    fn include_target_items(
+        &mut self,
+        item_type: &str,
+        item: &XmlElement,
+        include: &str,
+    ) -> Result<()> {
+        let defaults = self
+            .model
+            .get_item_definition(item_type)
+            .cloned()
+            .unwrap_or_default();
+        let exclude = item.attribute("Exclude");
+        let keep_metadata = item
+            .attribute("KeepMetadata")
+            .map(|names| self.item_specs(&names))
+            .transpose()?;
+        let remove_metadata = item
+            .attribute("RemoveMetadata")
+            .map(|names| self.item_specs(&names))
+            .transpose()?;
+        let keep_duplicates = match item.attribute("KeepDuplicates") {
+            Some(value) => ExpressionEvaluator::new(&self.model).evaluate_condition(&value)?,
+            None => true,
+        };
+
+        for mut created in self.create_items(item_type, include, exclude, &item.location)? {
+            let metadata = self.item_metadata(item, &created)?;
+            created.metadata.extend(metadata);
+            for (name, value) in &defaults {
+                created
+                    .metadata
+                    .entry(name.clone())
+                    .or_insert_with(|| value.clone());
+            }
+            if let Some(keep) = &keep_metadata {
+                created.metadata.retain(|name, _| keep.contains(name));
+            }
+            if let Some(remove) = &remove_metadata {
+                created.metadata.retain(|name, _| !remove.contains(name));
+            }
+            let items = self.model.items.entry(item_type.to_string()).or_default();
+            if !keep_duplicates
+                && items.iter().any(|existing| {
+                    existing.name == created.name && existing.metadata == created.metadata
+                })
+            {
+                continue;
+            }
+            // Defaults were applied before filtering, so bypass `add_item`
+            items.push(created);
+        }
+        Ok(())
     }
 
-    fn process_item(
+    fn remove_target_items(
         &mut self,
-        item_type: String,
-        include: String,
-        metadata: HashMap<String, String>,
+        item_type: &str,
+        item: &XmlElement,
+        remove: &str,
     ) -> Result<()> {
+        let Some(match_on) = item.attribute("MatchOnMetadata") else {
+            let specs = self.item_specs(remove)?;
+            self.model
+                .remove_items(item_type, |existing| matches_any(&specs, &existing.name));
+            return Ok(());
+        };
+
+        // Remove items whose metadata equals that of any referenced item
+        let names = self.item_specs(&match_on)?;
+        let reference_regex = Regex::new(r"@\(([^)]+)\)").unwrap();
+        let references: Vec<Item> = reference_regex
+            .captures_iter(remove)
+            .filter_map(|captures| self.model.get_items(captures[1].trim()))
+            .flatten()
+            .cloned()
+            .collect();
+        if references.is_empty() && !remove.trim().is_empty() {
+            return Err(item
+                .location
+                .error("MatchOnMetadata requires Remove to reference items, such as @(Other)"));
+        }
+        self.model.remove_items(item_type, |existing| {
+            references.iter().any(|reference| {
+                names
+                    .iter()
+                    .all(|name| existing.metadata.get(name) == reference.metadata.get(name))
+            })
+        });
+        Ok(())
+    }
+
+    fn process_property_group(&mut self, group: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(group)? {
+            return Ok(());
+        }
+        for (name, property) in group.msbuild_children() {
+            // Only set the property if there's no condition or condition is true
+            if !self.should_process_conditional(property)? {
+                continue;
+            }
+            // Expand properties now, so a property can build on its previous
+            // value. Item references are kept until the value is used, since
+            // items are evaluated after properties.
+            let evaluator = ExpressionEvaluator::new(&self.model);
+            let value = evaluator.expand_properties(property.text().trim());
+            self.model
+                .set_property_at(name.to_string(), value, property.location.clone())?;
+        }
+        Ok(())
+    }
+
+    fn process_item_group(&mut self, group: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(group)? {
+            return Ok(());
+        }
+        // Operations apply in document order, each seeing the result of the last
+        for (name, item) in group.msbuild_children() {
+            if !self.should_process_conditional(item)? {
+                continue;
+            }
+            if let Some(include) = item.attribute("Include") {
+                let exclude = item.attribute("Exclude");
+                for mut created in self.create_items(name, &include, exclude, &item.location)? {
+                    let metadata = self.item_metadata(item, &created)?;
+                    created.metadata.extend(metadata);
+                    self.model.add_item(created);
+                }
+            } else if let Some(remove) = item.attribute("Remove") {
+                let specs = self.item_specs(&remove)?;
+                self.model
+                    .remove_items(name, |existing| matches_any(&specs, &existing.name));
+            } else if let Some(update) = item.attribute("Update") {
+                self.update_items(name, item, &update)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Change the metadata of existing items matched by `Update`
+    fn update_items(&mut self, item_type: &str, element: &XmlElement, update: &str) -> Result<()> {
+        let specs = self.item_specs(update)?;
+        let Some(mut items) = self.model.get_items(item_type).cloned() else {
+            return Ok(());
+        };
+        for item in items
+            .iter_mut()
+            .filter(|item| matches_any(&specs, &item.name))
+        {
+            let metadata = self.item_metadata(element, item)?;
+            item.metadata.extend(metadata);
+        }
+        self.model.items.insert(item_type.to_string(), items);
+        Ok(())
+    }
+
+    /// Evaluate a `;` separated list of item specs
+    fn item_specs(&self, value: &str) -> Result<Vec<String>> {
         let evaluator = ExpressionEvaluator::new(&self.model);
-        let evaluated_include = evaluator.evaluate(&include)?;
-
-        // Handle multiple items separated by semicolons
-        for item_name in evaluated_include.split(';') {
-            if !item_name.trim().is_empty() {
-                let item = Item {
-                    item_type: item_type.clone(),
-                    name: item_name.trim().to_string(),
-                    metadata: metadata.clone(),
-                };
-                self.model.add_item(item);
+        Ok(evaluator
+            .evaluate(value)?
+            .split(';')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Metadata given as attributes or child elements of an item element for
+    /// one `item`. Values may refer to properties, to metadata the item already
+    /// has, such as defaults from item definitions, and to well-known metadata
+    /// such as `%(Filename)`.
+    fn item_metadata(&self, element: &XmlElement, item: &Item) -> Result<HashMap<String, String>> {
+        let mut known = item.clone();
+        for (name, value) in self
+            .model
+            .get_item_definition(&item.item_type)
+            .into_iter()
+            .flatten()
+        {
+            known
+                .metadata
+                .entry(name.clone())
+                .or_insert_with(|| value.clone());
+        }
+        let mut metadata = HashMap::new();
+
+        for attribute in &element.attributes {
+            if attribute.is_namespace_declaration()
+                || attribute.name.contains(':')
+                || ITEM_ATTRIBUTES.contains(&attribute.name.as_str())
+            {
+                continue;
+            }
+            let evaluator = ExpressionEvaluator::new(&self.model).with_item(&known);
+            let value = evaluator.evaluate(&attribute.value())?;
+            known.metadata.insert(attribute.name.clone(), value.clone());
+            metadata.insert(attribute.name.clone(), value);
+        }
+
+        for (name, child) in element.msbuild_children() {
+            let evaluator = ExpressionEvaluator::new(&self.model).with_item(&known);
+            if let Some(condition) = child.attribute("Condition")
+                && !evaluator.evaluate_condition(&condition)?
+            {
+                continue;
+            }
+            let value = evaluator.evaluate(child.text().trim())?;
+            known.metadata.insert(name.to_string(), value.clone());
+            metadata.insert(name.to_string(), value);
+        }
+
+        Ok(metadata)
+    }
+
+    fn process_item_definition_group(&mut self, group: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(group)? {
+            return Ok(());
+        }
+        for (item_type, definition) in group.msbuild_children() {
+            if !self.should_process_conditional(definition)? {
+                continue;
+            }
+            for (name, metadata) in definition.msbuild_children() {
+                if !self.should_process_conditional(metadata)? {
+                    continue;
+                }
+                let evaluator = ExpressionEvaluator::new(&self.model);
+                let value = evaluator.evaluate(metadata.text().trim())?;
+                self.model
+                    .add_item_definition_metadata(item_type, name.to_string(), value);
             }
         }
         Ok(())
     }
 
-    fn parse_attributes(&self, element: &BytesStart) -> Result<HashMap<String, String>> {
-        let mut attributes = HashMap::new();
+    /// Evaluate the first `When` whose condition holds, or else `Otherwise`
+    fn process_choose(&mut self, choose: &XmlElement) -> Result<()> {
+        for (name, branch) in choose.msbuild_children() {
+            match name {
+                "When" => {
+                    let condition = branch
+                        .attribute("Condition")
+                        .ok_or_else(|| branch.location.error("When missing Condition attribute"))?;
+                    let evaluator = ExpressionEvaluator::new(&self.model);
+                    if evaluator.evaluate_condition(&condition)? {
+                        return self.process_choose_branch(branch);
+                    }
+                }
+                "Otherwise" => return self.process_choose_branch(branch),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Properties of the chosen branch are set now; its items wait for the item pass
+    fn process_choose_branch(&mut self, branch: &XmlElement) -> Result<()> {
+        for (name, element) in branch.msbuild_children() {
+            match name {
+                "PropertyGroup" => self.process_property_group(element)?,
+                "ItemGroup" => self
+                    .deferred
+                    .item_groups
+                    .push((element.clone(), self.current_file())),
+                "Choose" => self.process_choose(element)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn create_target(&self, element: &XmlElement) -> Result<Target> {
+        let target_name = element
+            .attribute("Name")
+            .ok_or_else(|| element.location.error("Target missing Name attribute"))?;
+
+        let target_list = |attribute: &str| -> Vec<String> {
+            element
+                .attribute(attribute)
+                .map(|names| {
+                    names
+                        .split(';')
+                        .map(str::trim)
+                        .filter(|name| !name.is_empty())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let mut children = Vec::new();
+        let mut on_error = Vec::new();
+        for (name, child) in element.msbuild_children() {
+            if name == "OnError" {
+                let execute_targets = child.attribute("ExecuteTargets").ok_or_else(|| {
+                    child
+                        .location
+                        .error("OnError missing ExecuteTargets attribute")
+                })?;
+                on_error.push(OnError {
+                    execute_targets,
+                    condition: child.attribute("Condition"),
+                    location: child.location.clone(),
+                });
+                continue;
+            }
+            children.push(match name {
+                "PropertyGroup" => TargetChild::PropertyGroup(child.clone()),
+                "ItemGroup" => TargetChild::ItemGroup(child.clone()),
+                _ => TargetChild::Task(Task {
+                    name: name.to_string(),
+                    attributes: child.attribute_values(),
+                    condition: child.attribute("Condition"),
+                    outputs: self.task_outputs(child)?,
+                    location: child.location.clone(),
+                }),
+            });
+        }
+
+        Ok(Target {
+            name: target_name,
+            depends_on: target_list("DependsOnTargets"),
+            before_targets: target_list("BeforeTargets"),
+            after_targets: target_list("AfterTargets"),
+            inputs: element.attribute("Inputs"),
+            outputs: element.attribute("Outputs"),
+            returns: element.attribute("Returns"),
+            condition: element.attribute("Condition"),
+            children,
+            on_error,
+            location: element.location.clone(),
+        })
+    }
+
+    fn task_outputs(&self, task: &XmlElement) -> Result<Vec<TaskOutput>> {
+        let mut outputs = Vec::new();
+        for (_, output) in task
+            .msbuild_children()
+            .filter(|(name, _)| *name == "Output")
+        {
+            let task_parameter = output.attribute("TaskParameter").ok_or_else(|| {
+                output
+                    .location
+                    .error("Output missing TaskParameter attribute")
+            })?;
+            let property_name = output.attribute("PropertyName");
+            let item_name = output.attribute("ItemName");
+            if property_name.is_some() == item_name.is_some() {
+                return Err(output
+                    .location
+                    .error("Output needs exactly one of PropertyName and ItemName"));
+            }
+            outputs.push(TaskOutput {
+                task_parameter,
+                property_name,
+                item_name,
+                condition: output.attribute("Condition"),
+                location: output.location.clone(),
+            });
+        }
+        Ok(outputs)
+    }
+
+    fn process_import_group(&mut self, group: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(group)? {
+            return Ok(());
+        }
+        for (_, import) in group
+            .msbuild_children()
+            .filter(|(name, _)| *name == "Import")
+        {
+            self.process_import(import)?;
+        }
+        Ok(())
+    }
+
+    fn process_import(&mut self, element: &XmlElement) -> Result<()> {
+        if !self.should_process_conditional(element)? {
+            return Ok(());
+        }
+        let project = element
+            .attribute("Project")
+            .ok_or_else(|| element.location.error("Import missing Project attribute"))?;
+        let sdk = element.attribute("Sdk").map(|name| SdkReference {
+            name,
+            version: element.attribute("Version"),
+            minimum_version: element.attribute("MinimumVersion"),
+        });
+
+        // Paths are relative to the SDK or to the file containing the import
+        let directory = match &sdk {
+            Some(sdk) => self.resolve_sdk(sdk, &element.location)?,
+            None => self.current_directory(),
+        };
+        let evaluator = ExpressionEvaluator::new(&self.model);
+        let specs = evaluator.evaluate(&project)?;
+        self.model.add_import(Import {
+            project,
+            sdk,
+            condition: element.attribute("Condition"),
+            location: element.location.clone(),
+        });
+
+        for spec in specs
+            .split(';')
+            .map(str::trim)
+            .filter(|spec| !spec.is_empty())
+        {
+            if glob::has_wildcard(spec) {
+                for found in glob::expand(self.file_system.as_ref(), &directory, spec, &[])? {
+                    self.import_file(&directory.join(found.path), &element.location)?;
+                }
+                continue;
+            }
+            let path = directory.join(spec);
+            if self.file_system.exists(&path) {
+                self.import_file(&path, &element.location)?;
+            } else {
+                warn!(
+                    "{}: warning: Import file not found: {}",
+                    element.location,
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn import_sdk_file(&mut self, path: &Path, location: &SourceLocation) -> Result<()> {
+        if self.file_system.exists(path) {
+            self.import_file(path, location)?;
+        }
+        Ok(())
+    }
+
+    /// Evaluate an imported file in place, unless it is already being evaluated
+    /// or was imported before
+    fn import_file(&mut self, path: &Path, location: &SourceLocation) -> Result<()> {
+        let path = file_system::absolute(path);
+        if self.file_stack.contains(&path) {
+            warn!(
+                "{location}: warning: Circular import of {} ignored",
+                path.display()
+            );
+            return Ok(());
+        }
+        if self.imported.contains(&path) {
+            warn!(
+                "{location}: warning: {} was already imported; skipping duplicate import",
+                path.display()
+            );
+            return Ok(());
+        }
+
+        info!("Processing import: {}", path.display());
+        let root = ProjectRootElement::load_from(self.file_system.as_ref(), &path)?;
+        validation::enforce(&root, self.validation_mode)?;
+        self.evaluate_file(&root)
+    }
+
+    /// The directory of the file being evaluated
+    fn current_directory(&self) -> PathBuf {
+        self.file_stack
+            .last()
+            .and_then(|file| file.parent())
+            .map(Path::to_path_buf)
+            .unwrap_or_default()
+    }
 
-        for attr in element.attributes() {
-            let attr = attr?;
-            let key = std::str::from_utf8(attr.key.as_ref())?.to_string();
-            let value = std::str::from_utf8(&attr.value)?.to_string();
-            attributes.insert(key, value);
+    /// The directory of an SDK, or an error at `location` listing where it was looked for
+    fn resolve_sdk(&self, sdk: &SdkReference, location: &SourceLocation) -> Result<PathBuf> {
+        let project_directory = self.model.get_project_directory().unwrap_or_default();
+        let resolution = match &self.sdk_resolver {
+            Some(resolver) => resolver.resolve(sdk, &project_directory, self.file_system.as_ref()),
+            None => LocalSdkResolver::from_environment(&self.environment).resolve(
+                sdk,
+                &project_directory,
+                self.file_system.as_ref(),
+            ),
+        }?;
+        match resolution {
+            SdkResolution::Found(directory) => {
+                info!("Resolved SDK {sdk} to {}", directory.display());
+                Ok(directory)
+            }
+            SdkResolution::NotFound(searched) if searched.is_empty() => Err(location.error(
+                format!("Could not resolve SDK \"{sdk}\": no SDK directories are configured; set {SDKS_PATH_VARIABLE}"),
+            )),
+            SdkResolution::NotFound(searched) => {
+                let searched: Vec<String> = searched
+                    .iter()
+                    .map(|path| format!("  {}", path.display()))
+                    .collect();
+                Err(location.error(format!(
+                    "Could not resolve SDK \"{sdk}\". Searched:\n{}",
+                    searched.join("\n")
+                )))
+            }
+            SdkResolution::Rejected(reason) => Err(location.error(format!(
+                "Could not resolve SDK \"{sdk}\": {reason}"
+            ))),
         }
+    }
 
-        Ok(attributes)
+    fn process_using_task(&mut self, element: &XmlElement) {
+        if let (Some(task_name), Some(assembly)) = (
+            element.attribute("TaskName"),
+            element.attribute("AssemblyName"),
+        ) {
+            self.model.add_using_task(task_name, assembly);
+        }
     }
 
-    fn should_process_conditional(&self, attributes: &HashMap<String, String>) -> Result<bool> {
-        if let Some(condition) = attributes.get("Condition") {
+    /// The items an `Include` produces, minus those matching `Exclude`, before
+    /// the metadata of the item element is applied
+    fn create_items(
+        &self,
+        item_type: &str,
+        include: &str,
+        exclude: Option<String>,
+        location: &SourceLocation,
+    ) -> Result<Vec<Item>> {
+        let excluded = match exclude {
+            Some(exclude) => self.item_specs(&exclude)?,
+            None => Vec::new(),
+        };
+        let mut items = Vec::new();
+        for part in include.split(';').map(str::trim) {
+            // Items copied from another item type keep their metadata
+            if let Some(source_type) = part
+                .strip_prefix("@(")
+                .and_then(|rest| rest.strip_suffix(')'))
+                .filter(|name| !name.contains(['-', ',', '\'']))
+            {
+                for source in self.model.get_items(source_type).into_iter().flatten() {
+                    items.push((source.name.clone(), source.metadata.clone()));
+                }
+                continue;
+            }
+
             let evaluator = ExpressionEvaluator::new(&self.model);
-            evaluator.evaluate_condition(condition)
+            let evaluated = evaluator.evaluate(part)?;
+            // Handle multiple items separated by semicolons
+            for item_name in evaluated.split(';').map(str::trim) {
+                if item_name.is_empty() {
+                    continue;
+                }
+                if !glob::has_wildcard(item_name) {
+                    items.push((item_name.to_string(), HashMap::new()));
+                    continue;
+                }
+                let directory = self.model.get_project_directory().unwrap_or_default();
+                let found =
+                    glob::expand(self.file_system.as_ref(), &directory, item_name, &excluded)?;
+                for found in found {
+                    let metadata =
+                        HashMap::from([("RecursiveDir".to_string(), found.recursive_dir)]);
+                    items.push((found.path, metadata));
+                }
+            }
+        }
+
+        Ok(items
+            .into_iter()
+            .filter(|(name, _)| !matches_any(&excluded, name))
+            .map(|(name, metadata)| Item {
+                item_type: item_type.to_string(),
+                name,
+                metadata,
+                location: location.clone(),
+            })
+            .collect())
+    }
+
+    fn should_process_conditional(&self, element: &XmlElement) -> Result<bool> {
+        if let Some(condition) = element.attribute("Condition") {
+            let evaluator = ExpressionEvaluator::new(&self.model);
+            evaluator.evaluate_condition(&condition)
         } else {
             Ok(true)
         }
     }
 }
 
+/// Whether any of the item specs, which may contain wildcards, names the item
+fn matches_any(specs: &[String], name: &str) -> bool {
+    specs.iter().any(|spec| glob::matches(spec, name))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::file_system::InMemoryFileSystem;
     use std::io::Write;
     use tempfile::NamedTempFile;
 
@@ -306,4 +1025,446 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_source_locations() -> Result<()> {
+        let xml_content = r#"<Project>
+  <PropertyGroup>
+    <Configuration>Debug</Configuration>
+  </PropertyGroup>
+  <ItemGroup>
+    <Compile Include="Program.cs" />
+  </ItemGroup>
+  <Target Name="Build">
+      <Message Text="Hi" />
+  </Target>
+</Project>"#;
+
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(xml_content.as_bytes())?;
+
+        let mut parser = ProjectParser::new();
+        let model = parser.parse_file(temp_file.path())?;
+
+        let location = model.get_property_location("Configuration").unwrap();
+        assert_eq!((location.line, location.column), (3, 5));
+        assert_eq!(location.file, temp_file.path());
+
+        let item = &model.get_items("Compile").unwrap()[0];
+        assert_eq!((item.location.line, item.location.column), (6, 5));
+
+        let target = model.get_target("Build").unwrap();
+        let task = target.tasks().next().unwrap();
+        assert_eq!((target.location.line, target.location.column), (8, 3));
+        assert_eq!((task.location.line, task.location.column), (9, 7));
+        assert_eq!(
+            task.location.to_string(),
+            format!("{}(9,7)", temp_file.path().display())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors_report_location() -> Result<()> {
+        let xml_content = "<Project>\n  <Target DependsOnTargets=\"Compile\" />\n</Project>";
+
+        let mut temp_file = NamedTempFile::new()?;
+        temp_file.write_all(xml_content.as_bytes())?;
+
+        let mut parser = ProjectParser::new();
+        let error = parser.parse_file(temp_file.path()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{}(2,3): error: Target missing Name attribute",
+                temp_file.path().display()
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_from_str_and_reader() -> Result<()> {
+        let xml_content = "<Project><PropertyGroup><A>1</A></PropertyGroup></Project>";
+
+        let mut parser = ProjectParser::new();
+        let model = parser.parse_str(xml_content, PathBuf::from("buffer.proj"))?;
+        assert_eq!(model.get_property("A"), Some(&"1".to_string()));
+        assert_eq!(
+            model.get_property_location("A").unwrap().file,
+            PathBuf::from("buffer.proj")
+        );
+
+        let model = parser.parse_reader(xml_content.as_bytes(), PathBuf::from("reader.proj"))?;
+        assert_eq!(model.get_property("A"), Some(&"1".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_msbuild_namespace_and_prefixes() -> Result<()> {
+        let default_namespace = r#"<Project ToolsVersion="4.0" xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
+  <PropertyGroup><A>1</A></PropertyGroup>
+  <Target Name="Build"><Message Text="$(A)" /></Target>
+</Project>"#;
+        let prefixed = r#"<msb:Project xmlns:msb="http://schemas.microsoft.com/developer/msbuild/2003" xmlns:x="urn:other">
+  <msb:PropertyGroup><msb:A>1</msb:A><x:Ignored>2</x:Ignored></msb:PropertyGroup>
+  <x:Extension />
+  <msb:Target Name="Build"><msb:Message Text="$(A)" /></msb:Target>
+</msb:Project>"#;
+
+        for content in [default_namespace, prefixed] {
+            let mut parser = ProjectParser::new();
+            let model = parser.parse_str(content, PathBuf::from("ns.proj"))?;
+            assert_eq!(model.get_property("A"), Some(&"1".to_string()));
+            assert_eq!(model.properties.len(), 1);
+            let target = model.get_target("Build").unwrap();
+            let task = target.tasks().next().unwrap();
+            assert_eq!(task.name, "Message");
+            assert!(!task.attributes.contains_key("xmlns"));
+        }
+
+        let mut parser = ProjectParser::new();
+        let error = parser
+            .parse_str(r#"<Project xmlns="urn:other" />"#, PathBuf::from("x.proj"))
+            .unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .starts_with("x.proj(1,1): error: The root element must be <Project>")
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_choose_takes_first_matching_branch() -> Result<()> {
+        let xml_content = r#"<Project>
+  <PropertyGroup>
+    <Platform>x64</Platform>
+  </PropertyGroup>
+  <Choose>
+    <When Condition="'$(Platform)' == 'x86'">
+      <PropertyGroup><Bits>32</Bits></PropertyGroup>
+    </When>
+    <When Condition="'$(Platform)' == 'x64'">
+      <PropertyGroup><Bits>64</Bits></PropertyGroup>
+      <ItemGroup><Native Include="x64.dll" /></ItemGroup>
+      <Choose>
+        <When Condition="'$(Configuration)' == 'Debug'">
+          <PropertyGroup><Flavor>debug</Flavor></PropertyGroup>
+        </When>
+        <Otherwise>
+          <PropertyGroup><Flavor>other</Flavor></PropertyGroup>
+        </Otherwise>
+      </Choose>
+    </When>
+    <When Condition="'$(Bits)' == '64'">
+      <PropertyGroup><Bits>wrong</Bits></PropertyGroup>
+    </When>
+    <Otherwise>
+      <PropertyGroup><Bits>unknown</Bits></PropertyGroup>
+    </Otherwise>
+  </Choose>
+  <PropertyGroup>
+    <Configuration>Debug</Configuration>
+  </PropertyGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::new();
+        let model = parser.parse_str(xml_content, PathBuf::from("choose.proj"))?;
+        assert_eq!(model.get_property("Bits"), Some(&"64".to_string()));
+        assert_eq!(model.get_all_item_names("Native"), "x64.dll");
+        // Configuration is only defined after the Choose
+        assert_eq!(model.get_property("Flavor"), Some(&"other".to_string()));
+
+        let error = parser
+            .parse_str(
+                "<Project>\n  <Choose>\n    <When />\n  </Choose>\n</Project>",
+                PathBuf::from("choose.proj"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "choose.proj(3,5): error: When missing Condition attribute"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_item_definitions_give_default_metadata() -> Result<()> {
+        let xml_content = r#"<Project>
+  <PropertyGroup>
+    <Configuration>Release</Configuration>
+  </PropertyGroup>
+  <ItemGroup>
+    <Compile Include="early.cs" />
+  </ItemGroup>
+  <ItemDefinitionGroup>
+    <Compile>
+      <Optimize Condition="'$(Configuration)' == 'Release'">true</Optimize>
+      <Warnings Condition="'$(Configuration)' == 'Debug'">all</Warnings>
+      <Output>bin/$(Configuration)</Output>
+    </Compile>
+    <None Condition="'$(Configuration)' == 'Debug'">
+      <Copy>always</Copy>
+    </None>
+  </ItemDefinitionGroup>
+  <ItemGroup>
+    <Compile Include="late.cs" />
+    <None Include="readme.txt" />
+  </ItemGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::new();
+        let mut model = parser.parse_str(xml_content, PathBuf::from("defs.proj"))?;
+        for item in model.get_items("Compile").unwrap() {
+            assert_eq!(item.metadata.get("Optimize"), Some(&"true".to_string()));
+            assert_eq!(
+                item.metadata.get("Output"),
+                Some(&"bin/Release".to_string())
+            );
+            assert_eq!(item.metadata.get("Warnings"), None);
+        }
+        assert!(model.get_items("None").unwrap()[0].metadata.is_empty());
+
+        model.add_item(Item {
+            item_type: "Compile".to_string(),
+            name: "explicit.cs".to_string(),
+            metadata: HashMap::from([("Optimize".to_string(), "false".to_string())]),
+            location: SourceLocation::default(),
+        });
+        let explicit = &model.get_items("Compile").unwrap()[2];
+        assert_eq!(
+            explicit.metadata.get("Optimize"),
+            Some(&"false".to_string())
+        );
+        assert_eq!(
+            explicit.metadata.get("Output"),
+            Some(&"bin/Release".to_string())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_item_metadata_from_attributes_and_elements() -> Result<()> {
+        let xml_content = r#"<Project>
+  <PropertyGroup>
+    <Root>src</Root>
+  </PropertyGroup>
+  <ItemDefinitionGroup>
+    <Compile><Folder>generated</Folder></Compile>
+  </ItemDefinitionGroup>
+  <ItemGroup>
+    <Compile Include="a.cs;b.cs" Link="$(Root)/%(Folder)">
+      <Visible>false</Visible>
+      <Skipped Condition="'$(Root)' == 'lib'">yes</Skipped>
+      <Target>%(Link)/out</Target>
+    </Compile>
+    <Copied Include="@(Compile);extra.cs" Visible="true" />
+  </ItemGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::new();
+        let model = parser.parse_str(xml_content, PathBuf::from("metadata.proj"))?;
+
+        let compile = model.get_items("Compile").unwrap();
+        assert_eq!(compile.len(), 2);
+        for item in compile {
+            let metadata = |name: &str| item.metadata.get(name).map(String::as_str);
+            assert_eq!(metadata("Link"), Some("src/generated"));
+            assert_eq!(metadata("Visible"), Some("false"));
+            assert_eq!(metadata("Skipped"), None);
+            assert_eq!(metadata("Target"), Some("src/generated/out"));
+        }
+
+        let copied = model.get_items("Copied").unwrap();
+        assert_eq!(model.get_all_item_names("Copied"), "a.cs;b.cs;extra.cs");
+        assert_eq!(
+            copied[0].metadata.get("Link"),
+            Some(&"src/generated".to_string())
+        );
+        assert_eq!(copied[0].metadata.get("Visible"), Some(&"true".to_string()));
+        assert_eq!(copied[2].metadata.get("Link"), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_item_operations_apply_in_document_order() -> Result<()> {
+        let xml_content = r#"<Project>
+  <PropertyGroup>
+    <Generated>gen.cs</Generated>
+  </PropertyGroup>
+  <ItemGroup>
+    <Compile Include="a.cs;b.cs;gen.cs;c.cs" Exclude="c.cs" Link="x" />
+    <Compile Include="debug.cs" Condition="'$(Configuration)' == 'Debug'" />
+    <Compile Remove="$(Generated)" />
+    <Compile Update="a.cs;.\B.cs" Link="linked/%(Link)">
+      <Visible>false</Visible>
+    </Compile>
+    <Compile Include="gen.cs" />
+    <Other Include="@(Compile)" Exclude="a.cs" />
+    <Compile Remove="@(Other)" />
+  </ItemGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::new();
+        let model = parser.parse_str(xml_content, PathBuf::from("ops.proj"))?;
+
+        assert_eq!(model.get_all_item_names("Other"), "b.cs;gen.cs");
+        let compile = model.get_items("Compile").unwrap();
+        assert_eq!(model.get_all_item_names("Compile"), "a.cs");
+        assert_eq!(
+            compile[0].metadata.get("Visible"),
+            Some(&"false".to_string())
+        );
+        let other = model.get_items("Other").unwrap();
+        assert_eq!(other[0].metadata.get("Visible"), Some(&"false".to_string()));
+        // gen.cs was removed and included again without metadata
+        assert!(other[1].metadata.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wildcard_items() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file("/repo/Program.cs", "")
+                .with_file("/repo/src/Util.cs", "")
+                .with_file("/repo/src/Generated/Api.cs", "")
+                .with_file("/repo/obj/Temp.cs", ""),
+        );
+        let xml_content = r#"<Project>
+  <ItemGroup>
+    <Compile Include="**/*.cs" Exclude="obj/**" />
+    <Compile Remove="src/generated/**" />
+    <Content Include="missing/*.txt" />
+  </ItemGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::with_file_system(fs);
+        let model = parser.parse_str(xml_content, PathBuf::from("/repo/app.proj"))?;
+
+        assert_eq!(
+            model.get_all_item_names("Compile"),
+            "Program.cs;src/Util.cs"
+        );
+        let compile = model.get_items("Compile").unwrap();
+        assert_eq!(
+            compile[1].metadata.get("RecursiveDir"),
+            Some(&"src/".to_string())
+        );
+        assert!(model.get_items("Content").is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluation_passes() -> Result<()> {
+        let xml_content = r#"<Project>
+  <ItemGroup>
+    <Compile Include="$(Name).cs" Condition="'$(Configuration)' == 'Release'" />
+  </ItemGroup>
+  <PropertyGroup>
+    <DefineConstants>DEBUG</DefineConstants>
+    <DefineConstants>$(DefineConstants);TRACE</DefineConstants>
+    <Sources>@(Compile)</Sources>
+    <Name>early</Name>
+    <Label>$(Name)</Label>
+    <Name>app</Name>
+    <Configuration>Release</Configuration>
+    <Empty />
+    <AlsoEmpty></AlsoEmpty>
+  </PropertyGroup>
+  <ItemDefinitionGroup>
+    <Compile><Kind>code</Kind></Compile>
+  </ItemDefinitionGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::new();
+        let model = parser.parse_str(xml_content, PathBuf::from("passes.proj"))?;
+        let property = |name| model.get_property(name).map(String::as_str);
+
+        assert_eq!(property("DefineConstants"), Some("DEBUG;TRACE"));
+        // Properties are expanded with the values they had when defined
+        assert_eq!(property("Label"), Some("early"));
+        assert_eq!(property("Empty"), Some(""));
+        assert_eq!(property("AlsoEmpty"), Some(""));
+        // Items see every property and item definition, wherever they appear
+        let compile = model.get_items("Compile").unwrap();
+        assert_eq!(compile[0].name, "app.cs");
+        assert_eq!(compile[0].metadata.get("Kind"), Some(&"code".to_string()));
+        // Item references in properties are expanded when the property is used
+        assert_eq!(property("Sources"), Some("@(Compile)"));
+        let evaluator = ExpressionEvaluator::new(&model);
+        assert_eq!(evaluator.evaluate("$(Sources)")?, "app.cs");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_metadata_can_use_well_known_metadata() -> Result<()> {
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file("/repo/src/a.cs", "")
+                .with_file("/repo/src/util/b.cs", ""),
+        );
+        let xml_content = r#"<Project>
+  <ItemGroup>
+    <Compile Include="src/a.cs" Link="linked/%(Filename)%(Extension)" />
+    <Content Include="src/**/*.cs" />
+    <Content Update="src/**/*.cs" Link="%(RecursiveDir)%(Filename)%(Extension)" />
+  </ItemGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::with_file_system(fs);
+        let model = parser.parse_str(xml_content, PathBuf::from("/repo/app.proj"))?;
+        let links = |item_type| -> Vec<String> {
+            model
+                .get_items(item_type)
+                .unwrap()
+                .iter()
+                .map(|item| item.metadata["Link"].clone())
+                .collect()
+        };
+
+        assert_eq!(links("Compile"), ["linked/a.cs"]);
+        assert_eq!(links("Content"), ["a.cs", "util/b.cs"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_relative_project_path() -> Result<()> {
+        let directory = std::env::current_dir()?;
+        let fs = Arc::new(
+            InMemoryFileSystem::new()
+                .with_file(directory.join("src/a.cs"), "")
+                .with_file(directory.join("src/b.cs"), "")
+                .with_file(
+                    directory.join("common.props"),
+                    "<Project><PropertyGroup><Common>yes</Common></PropertyGroup></Project>",
+                ),
+        );
+        let xml_content = r#"<Project>
+  <Import Project="*.props" />
+  <ItemGroup>
+    <Compile Include="**/*.cs" />
+  </ItemGroup>
+</Project>"#;
+
+        let mut parser = ProjectParser::with_file_system(fs);
+        let model = parser.parse_str(xml_content, PathBuf::from("app.proj"))?;
+
+        assert_eq!(model.get_all_item_names("Compile"), "src/a.cs;src/b.cs");
+        assert_eq!(model.get_property("Common"), Some(&"yes".to_string()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,261 @@
+//! Properties that do not come from project files.
+//!
+//! MSBuild defines reserved properties describing the project, the file being
+//! evaluated and the tools. Projects can read them but not set them. Environment
+//! variables are visible as properties too, with lower precedence than anything
+//! a project defines. Global properties, given on the command line, override
+//! both.
+
+use anyhow::{Result, anyhow, bail};
+use indexmap::IndexMap;
+use std::path::{Component, Path, PathBuf};
+
+use crate::file_system;
+
+/// Reserved property names; comparisons ignore case, as MSBuild's do
+pub const RESERVED_PROPERTIES: &[&str] = &[
+    "MSBuildProjectDirectory",
+    "MSBuildProjectDirectoryNoRoot",
+    "MSBuildProjectExtension",
+    "MSBuildProjectFile",
+    "MSBuildProjectFullPath",
+    "MSBuildProjectName",
+    "MSBuildThisFile",
+    "MSBuildThisFileDirectory",
+    "MSBuildThisFileDirectoryNoRoot",
+    "MSBuildThisFileExtension",
+    "MSBuildThisFileFullPath",
+    "MSBuildThisFileName",
+    "MSBuildStartupDirectory",
+    "MSBuildToolsPath",
+    "MSBuildToolsVersion",
+    "MSBuildBinPath",
+    "MSBuildNodeCount",
+    "MSBuildLastTaskResult",
+    "MSBuildVersion",
+    "MSBuildRuntimeType",
+];
+
+/// The version reported by `$(MSBuildToolsVersion)`, as in current MSBuild
+pub const TOOLS_VERSION: &str = "Current";
+
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED_PROPERTIES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(name))
+}
+
+/// `MSBuildProject*` properties of the project being built
+pub fn project_properties(path: &Path) -> Vec<(&'static str, String)> {
+    let parts = PathParts::new(path);
+    vec![
+        ("MSBuildProjectFullPath", parts.full_path),
+        ("MSBuildProjectDirectory", parts.directory),
+        ("MSBuildProjectDirectoryNoRoot", parts.directory_no_root),
+        ("MSBuildProjectFile", parts.file),
+        ("MSBuildProjectName", parts.name),
+        ("MSBuildProjectExtension", parts.extension),
+    ]
+}
+
+/// `MSBuildThisFile*` properties of the file being evaluated, which is the
+/// project itself or one of its imports. Directories end with a separator.
+pub fn this_file_properties(path: &Path) -> Vec<(&'static str, String)> {
+    let parts = PathParts::new(path);
+    let with_separator = |directory: String| {
+        if directory.is_empty() || directory.ends_with(std::path::MAIN_SEPARATOR) {
+            directory
+        } else {
+            format!("{directory}{}", std::path::MAIN_SEPARATOR)
+        }
+    };
+    vec![
+        ("MSBuildThisFileFullPath", parts.full_path),
+        ("MSBuildThisFileDirectory", with_separator(parts.directory)),
+        (
+            "MSBuildThisFileDirectoryNoRoot",
+            with_separator(parts.directory_no_root),
+        ),
+        ("MSBuildThisFile", parts.file),
+        ("MSBuildThisFileName", parts.name),
+        ("MSBuildThisFileExtension", parts.extension),
+    ]
+}
+
+/// Properties describing this tool and where it was started
+pub fn tool_properties() -> Vec<(&'static str, String)> {
+    let startup_directory = std::env::current_dir().unwrap_or_default();
+    let tools_path = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_default();
+    vec![
+        (
+            "MSBuildStartupDirectory",
+            startup_directory.display().to_string(),
+        ),
+        ("MSBuildToolsPath", tools_path.display().to_string()),
+        ("MSBuildBinPath", tools_path.display().to_string()),
+        ("MSBuildToolsVersion", TOOLS_VERSION.to_string()),
+        ("MSBuildVersion", env!("CARGO_PKG_VERSION").to_string()),
+        ("MSBuildRuntimeType", "Core".to_string()),
+        ("MSBuildNodeCount", "1".to_string()),
+    ]
+}
+
+/// Whether `name` can be used as a property name: a letter or underscore
+/// followed by letters, digits, underscores and hyphens
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|first| first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+/// Properties for environment variables, skipping names that are not valid
+/// property names (such as `ProgramFiles(x86)`) or that are reserved
+pub fn environment_properties(
+    variables: impl IntoIterator<Item = (String, String)>,
+) -> IndexMap<String, String> {
+    variables
+        .into_iter()
+        .filter(|(name, _)| is_valid_name(name) && !is_reserved(name))
+        .collect()
+}
+
+/// The environment of this process, without variables that are not Unicode
+pub fn process_environment() -> IndexMap<String, String> {
+    environment_properties(
+        std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }),
+    )
+}
+
+/// Parse the value of a `-p:` switch: `Name=Value` pairs separated by `;` or
+/// `,`. Double quotes keep separators in a value and are removed.
+pub fn parse_global_properties(switch: &str) -> Result<Vec<(String, String)>> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in switch.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' | ',' if !quoted => parts.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    parts.push(current);
+
+    parts
+        .into_iter()
+        .filter(|part| !part.trim().is_empty())
+        .map(|part| {
+            let (name, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("expected Name=Value in property switch, got `{part}`"))?;
+            let name = name.trim();
+            if !is_valid_name(name) {
+                bail!("\"{name}\" is not a valid property name");
+            }
+            Ok((name.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+struct PathParts {
+    full_path: String,
+    directory: String,
+    directory_no_root: String,
+    file: String,
+    name: String,
+    extension: String,
+}
+
+impl PathParts {
+    fn new(path: &Path) -> Self {
+        let full_path = file_system::absolute(path);
+        let directory = full_path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let directory_no_root: PathBuf = directory
+            .components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let text = |path: &Path| path.display().to_string();
+        let os_text = |part: Option<&std::ffi::OsStr>| {
+            part.map(|part| part.to_string_lossy().into_owned())
+                .unwrap_or_default()
+        };
+        Self {
+            full_path: text(&full_path),
+            directory: text(&directory),
+            directory_no_root: text(&directory_no_root),
+            file: os_text(full_path.file_name()),
+            name: os_text(full_path.file_stem()),
+            extension: full_path
+                .extension()
+                .map(|extension| format!(".{}", extension.to_string_lossy()))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_properties() {
+        let project = project_properties(Path::new("/repo/src/../app.csproj"));
+        assert_eq!(
+            project,
+            [
+                ("MSBuildProjectFullPath", "/repo/app.csproj".to_string()),
+                ("MSBuildProjectDirectory", "/repo".to_string()),
+                ("MSBuildProjectDirectoryNoRoot", "repo".to_string()),
+                ("MSBuildProjectFile", "app.csproj".to_string()),
+                ("MSBuildProjectName", "app".to_string()),
+                ("MSBuildProjectExtension", ".csproj".to_string()),
+            ]
+        );
+
+        let this_file = this_file_properties(Path::new("/repo/build/common.props"));
+        assert!(this_file.contains(&("MSBuildThisFileDirectory", "/repo/build/".to_string())));
+        assert!(this_file.contains(&("MSBuildThisFileDirectoryNoRoot", "repo/build/".to_string())));
+        assert!(is_reserved("msbuildprojectname"));
+        assert!(!is_reserved("Configuration"));
+    }
+
+    #[test]
+    fn test_environment_properties_skip_invalid_names() {
+        let variables = [
+            ("HOME", "/home/me"),
+            ("ProgramFiles(x86)", "C:\\Program Files (x86)"),
+            ("=C:", "C:\\"),
+            ("1ST", "x"),
+            ("_my-var", "y"),
+            ("MSBuildProjectName", "z"),
+        ];
+        let properties =
+            environment_properties(variables.map(|(n, v)| (n.to_string(), v.to_string())));
+        let names: Vec<_> = properties.keys().map(String::as_str).collect();
+        assert_eq!(names, ["HOME", "_my-var"]);
+    }
+
+    #[test]
+    fn test_parse_global_properties() -> Result<()> {
+        let properties =
+            parse_global_properties(r#"Configuration=Release;Platform=x64,Defines="A;B""#)?;
+        let expected = [
+            ("Configuration", "Release"),
+            ("Platform", "x64"),
+            ("Defines", "A;B"),
+        ]
+        .map(|(n, v)| (n.to_string(), v.to_string()));
+        assert_eq!(properties, expected);
+
+        assert!(parse_global_properties("Configuration").is_err());
+        assert!(parse_global_properties("1st=x").is_err());
+        Ok(())
+    }
+}
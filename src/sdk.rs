@@ -0,0 +1,273 @@
+//! Project SDKs such as `Microsoft.NET.Sdk`.
+//!
+//! `<Project Sdk="...">` and `<Sdk Name="..."/>` import `Sdk.props` before the
+//! project body and `Sdk.targets` after it, and `<Import Sdk="..."/>` imports a
+//! file from an SDK explicitly. An [`SdkResolver`] finds the directory holding
+//! those files. The built-in [`LocalSdkResolver`] searches local SDK directories
+//! and honors versions pinned in the `msbuild-sdks` section of `global.json`.
+
+use anyhow::{Result, anyhow};
+use indexmap::IndexMap;
+use std::cmp::Ordering;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::construction::XmlElement;
+use crate::file_system::{self, FileSystem};
+
+/// The environment variable listing SDK directories, like MSBuild's
+pub const SDKS_PATH_VARIABLE: &str = "MSBuildSDKsPath";
+
+/// An SDK named by a project, with an optional version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SdkReference {
+    pub name: String,
+    pub version: Option<String>,
+    pub minimum_version: Option<String>,
+}
+
+impl SdkReference {
+    /// Parse `Name`, `Name/Version` or `Name/min=Version` as written in `Sdk` attributes
+    pub fn parse(spec: &str) -> Self {
+        let (name, version) = match spec.split_once('/') {
+            Some((name, version)) => (name.trim(), Some(version.trim())),
+            None => (spec.trim(), None),
+        };
+        let (version, minimum_version) = match version {
+            Some(version) => match version.strip_prefix("min=") {
+                Some(minimum) => (None, Some(minimum.to_string())),
+                None => (Some(version.to_string()), None),
+            },
+            None => (None, None),
+        };
+        Self {
+            name: name.to_string(),
+            version: version.filter(|v| !v.is_empty()),
+            minimum_version,
+        }
+    }
+}
+
+impl fmt::Display for SdkReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.version, &self.minimum_version) {
+            (Some(version), _) => write!(f, "{}/{version}", self.name),
+            (None, Some(minimum)) => write!(f, "{}/min={minimum}", self.name),
+            (None, None) => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// The SDKs a project element names through its `Sdk` attribute and `<Sdk>` children
+pub fn project_sdks(project: &XmlElement) -> Result<Vec<SdkReference>> {
+    let mut sdks: Vec<SdkReference> = project
+        .attribute("Sdk")
+        .iter()
+        .flat_map(|specs| specs.split(';'))
+        .filter(|spec| !spec.trim().is_empty())
+        .map(SdkReference::parse)
+        .collect();
+    for (_, element) in project
+        .msbuild_children()
+        .filter(|(name, _)| *name == "Sdk")
+    {
+        let name = element
+            .attribute("Name")
+            .ok_or_else(|| element.location.error("Sdk missing Name attribute"))?;
+        sdks.push(SdkReference {
+            name,
+            version: element.attribute("Version"),
+            minimum_version: element.attribute("MinimumVersion"),
+        });
+    }
+    Ok(sdks)
+}
+
+pub enum SdkResolution {
+    /// The directory containing `Sdk.props` and `Sdk.targets`
+    Found(PathBuf),
+    /// The locations that were searched, for the error message
+    NotFound(Vec<PathBuf>),
+    /// An SDK was found but cannot be used, for the reason given
+    Rejected(String),
+}
+
+pub trait SdkResolver: Send + Sync {
+    fn resolve(
+        &self,
+        sdk: &SdkReference,
+        project_directory: &Path,
+        file_system: &dyn FileSystem,
+    ) -> Result<SdkResolution>;
+}
+
+/// Resolves SDKs from local directories laid out as `<root>/<Name>/<Version>/Sdk`
+/// or, when no version is requested or pinned, `<root>/<Name>/Sdk`
+#[derive(Debug, Clone, Default)]
+pub struct LocalSdkResolver {
+    roots: Vec<PathBuf>,
+}
+
+impl LocalSdkResolver {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Self { roots }
+    }
+
+    /// Search the directories listed in the `MSBuildSDKsPath` variable of
+    /// `environment`, which is empty for hermetic builds
+    pub fn from_environment(environment: &IndexMap<String, String>) -> Self {
+        let roots = environment
+            .get(SDKS_PATH_VARIABLE)
+            .map(|paths| std::env::split_paths(paths).collect())
+            .unwrap_or_default();
+        Self::new(roots)
+    }
+}
+
+impl SdkResolver for LocalSdkResolver {
+    fn resolve(
+        &self,
+        sdk: &SdkReference,
+        project_directory: &Path,
+        file_system: &dyn FileSystem,
+    ) -> Result<SdkResolution> {
+        let version = match &sdk.version {
+            Some(version) => Some(version.clone()),
+            None => pinned_version(&sdk.name, project_directory, file_system)?,
+        };
+        if let (Some(version), Some(minimum)) = (&version, &sdk.minimum_version)
+            && compare_versions(version, minimum) == Ordering::Less
+        {
+            return Ok(SdkResolution::Rejected(format!(
+                "version {version} is below the minimum version {minimum}"
+            )));
+        }
+        // A requested version must be found; another version would be a
+        // different SDK than the one asked for
+        let searched: Vec<PathBuf> = self
+            .roots
+            .iter()
+            .map(|root| match &version {
+                Some(version) => root.join(&sdk.name).join(version).join("Sdk"),
+                None => root.join(&sdk.name).join("Sdk"),
+            })
+            .collect();
+        match searched.iter().find(|path| file_system.exists(path)) {
+            Some(found) => Ok(SdkResolution::Found(found.clone())),
+            None => Ok(SdkResolution::NotFound(searched)),
+        }
+    }
+}
+
+/// Compare dotted versions numerically part by part, such as `2.10` > `2.9`.
+/// Parts that are not numbers compare as text.
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let mut a_parts = a.split('.');
+    let mut b_parts = b.split('.');
+    loop {
+        let ordering = match (a_parts.next(), b_parts.next()) {
+            (None, None) => return Ordering::Equal,
+            (a, b) => {
+                let (a, b) = (a.unwrap_or("0"), b.unwrap_or("0"));
+                match (a.parse::<u64>(), b.parse::<u64>()) {
+                    (Ok(a), Ok(b)) => a.cmp(&b),
+                    _ => a.cmp(b),
+                }
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// The version of an SDK pinned in the `msbuild-sdks` section of the nearest
+/// `global.json` above the project, searching above the current directory when
+/// the project directory is relative
+fn pinned_version(
+    name: &str,
+    project_directory: &Path,
+    file_system: &dyn FileSystem,
+) -> Result<Option<String>> {
+    let Some(global_json) = file_system::absolute(project_directory)
+        .ancestors()
+        .map(|directory| directory.join("global.json"))
+        .find(|path| file_system.exists(path))
+    else {
+        return Ok(None);
+    };
+    let contents = file_system.read(&global_json)?;
+    let json: serde_json::Value = serde_json::from_slice(&contents)
+        .map_err(|e| anyhow!("{}: invalid JSON: {e}", global_json.display()))?;
+    Ok(json
+        .get("msbuild-sdks")
+        .and_then(|sdks| sdks.get(name))
+        .and_then(|version| version.as_str())
+        .map(str::to_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::file_system::InMemoryFileSystem;
+
+    #[test]
+    fn test_parse_sdk_reference() {
+        let sdk = SdkReference::parse("My.Sdk/1.2.3");
+        assert_eq!(sdk.name, "My.Sdk");
+        assert_eq!(sdk.version.as_deref(), Some("1.2.3"));
+        assert_eq!(sdk.to_string(), "My.Sdk/1.2.3");
+
+        let sdk = SdkReference::parse(" My.Sdk/min=2.0 ");
+        assert_eq!(sdk.version, None);
+        assert_eq!(sdk.minimum_version.as_deref(), Some("2.0"));
+        assert_eq!(SdkReference::parse("My.Sdk").to_string(), "My.Sdk");
+    }
+
+    #[test]
+    fn test_local_resolver_honors_global_json() -> Result<()> {
+        let fs = InMemoryFileSystem::new()
+            .with_file("/sdks/My.Sdk/Sdk/Sdk.props", "")
+            .with_file("/sdks/My.Sdk/2.0.0/Sdk/Sdk.props", "")
+            .with_file(
+                "/repo/global.json",
+                r#"{ "msbuild-sdks": { "My.Sdk": "2.0.0" } }"#,
+            );
+        let resolver = LocalSdkResolver::new(vec![PathBuf::from("/other"), PathBuf::from("/sdks")]);
+        let resolve = |spec: &str, directory: &str| {
+            resolver.resolve(&SdkReference::parse(spec), Path::new(directory), &fs)
+        };
+
+        match resolve("My.Sdk", "/repo/src")? {
+            SdkResolution::Found(path) => assert_eq!(path, Path::new("/sdks/My.Sdk/2.0.0/Sdk")),
+            _ => panic!("pinned version not found"),
+        }
+        match resolve("My.Sdk", "/elsewhere")? {
+            SdkResolution::Found(path) => assert_eq!(path, Path::new("/sdks/My.Sdk/Sdk")),
+            _ => panic!("unversioned SDK not found"),
+        }
+        // A requested version does not fall back to the unversioned SDK
+        match resolve("My.Sdk/3.0", "/elsewhere")? {
+            SdkResolution::NotFound(searched) => assert_eq!(
+                searched,
+                [
+                    Path::new("/other/My.Sdk/3.0/Sdk"),
+                    Path::new("/sdks/My.Sdk/3.0/Sdk"),
+                ]
+            ),
+            _ => panic!("resolved a version that was not requested"),
+        }
+        match resolve("My.Sdk/min=2.10", "/repo/src")? {
+            SdkResolution::Rejected(reason) => {
+                assert_eq!(reason, "version 2.0.0 is below the minimum version 2.10")
+            }
+            _ => panic!("pinned version below the minimum was accepted"),
+        }
+        assert!(matches!(
+            resolve("My.Sdk/min=1.9", "/repo/src")?,
+            SdkResolution::Found(_)
+        ));
+
+        Ok(())
+    }
+}
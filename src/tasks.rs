@@ -1,18 +1,22 @@
 use anyhow::{Result, anyhow};
 use log::{error, info};
 use std::collections::HashMap;
-use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use crate::object_model::{ProjectModel, Task};
+use crate::file_system::{FileSystem, PhysicalFileSystem};
+use crate::object_model::{ProjectModel, SourceLocation, Task};
 
 /// Context passed to task executors containing all necessary execution parameters
-#[derive(Debug)]
 pub struct TaskExecutionContext {
     /// Pre-evaluated attribute values from the task XML
     pub attributes: HashMap<String, String>,
     /// The directory containing the executing project file
     pub project_directory: PathBuf,
+    /// Where the task element appears, for located diagnostics
+    pub location: SourceLocation,
+    /// The only way tasks may touch files
+    pub file_system: Arc<dyn FileSystem>,
 }
 
 impl TaskExecutionContext {
@@ -20,31 +24,65 @@ impl TaskExecutionContext {
         Self {
             attributes,
             project_directory,
+            location: SourceLocation::default(),
+            file_system: PhysicalFileSystem::shared(),
         }
     }
+
+    pub fn with_file_system(mut self, file_system: Arc<dyn FileSystem>) -> Self {
+        self.file_system = file_system;
+        self
+    }
+
+    pub fn with_location(mut self, location: SourceLocation) -> Self {
+        self.location = location;
+        self
+    }
+}
+
+/// Output parameters a task produced, by name. Each is a list of values that
+/// `<Output>` elements turn into a property or items.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TaskOutputs {
+    parameters: HashMap<String, Vec<String>>,
+}
+
+impl TaskOutputs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, name: &str, values: Vec<String>) -> Self {
+        self.parameters.insert(name.to_string(), values);
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.parameters.get(name).map(Vec::as_slice)
+    }
 }
 
 pub trait TaskExecutor {
-    fn execute(&self, context: &TaskExecutionContext) -> Result<()>;
+    fn execute(&self, context: &TaskExecutionContext) -> Result<TaskOutputs>;
 }
 
 pub struct MessageTask;
 
 impl TaskExecutor for MessageTask {
-    fn execute(&self, context: &TaskExecutionContext) -> Result<()> {
+    fn execute(&self, context: &TaskExecutionContext) -> Result<TaskOutputs> {
         if let Some(text) = context.attributes.get("Text") {
             info!("{text}");
         }
-        Ok(())
+        Ok(TaskOutputs::new())
     }
 }
 
 pub struct ErrorTask;
 
 impl TaskExecutor for ErrorTask {
-    fn execute(&self, context: &TaskExecutionContext) -> Result<()> {
+    fn execute(&self, context: &TaskExecutionContext) -> Result<TaskOutputs> {
         if let Some(text) = context.attributes.get("Text") {
-            error!("{text}");
+            error!("{}: error: {text}", context.location);
             return Err(anyhow!("Build failed: {text}"));
         }
 
@@ -54,8 +92,10 @@ impl TaskExecutor for ErrorTask {
 
 pub struct CopyTask;
 
+/// Outputs `CopiedFiles`, the sources that were copied, and `DestinationFiles`,
+/// where they were copied to
 impl TaskExecutor for CopyTask {
-    fn execute(&self, context: &TaskExecutionContext) -> Result<()> {
+    fn execute(&self, context: &TaskExecutionContext) -> Result<TaskOutputs> {
         let source_files = context
             .attributes
             .get("SourceFiles")
@@ -74,7 +114,11 @@ impl TaskExecutor for CopyTask {
         };
 
         // Create destination directory if it doesn't exist
-        fs::create_dir_all(&dest_path)?;
+        let fs = context.file_system.as_ref();
+        fs.create_dir_all(&dest_path)?;
+
+        let mut copied_files = Vec::new();
+        let mut destination_files = Vec::new();
 
         // Copy each file
         for source_file in source_files.split(';') {
@@ -89,25 +133,33 @@ impl TaskExecutor for CopyTask {
                 context.project_directory.join(source_file.trim())
             };
 
-            if source_path.exists() {
+            if fs.exists(&source_path) {
                 let file_name = source_path
                     .file_name()
                     .ok_or_else(|| anyhow!("Invalid source file path: {}", source_file))?;
 
                 let final_dest_path = dest_path.join(file_name);
 
-                fs::copy(&source_path, &final_dest_path)?;
+                fs.copy(&source_path, &final_dest_path)?;
                 info!(
                     "Copied {} to {}",
                     source_path.display(),
                     final_dest_path.display()
                 );
+                copied_files.push(source_file.trim().to_string());
+                destination_files.push(final_dest_path.to_string_lossy().into_owned());
             } else {
-                error!("Source file does not exist: {}", source_path.display());
+                error!(
+                    "{}: error: Source file does not exist: {}",
+                    context.location,
+                    source_path.display()
+                );
             }
         }
 
-        Ok(())
+        Ok(TaskOutputs::new()
+            .with("CopiedFiles", copied_files)
+            .with("DestinationFiles", destination_files))
     }
 }
 
@@ -133,14 +185,19 @@ impl TaskRegistry {
         self.tasks.insert(name.to_string(), executor);
     }
 
-    pub fn execute_task(&self, task: &Task, model: &ProjectModel) -> Result<()> {
+    pub fn execute_task(
+        &self,
+        task: &Task,
+        model: &ProjectModel,
+        file_system: &Arc<dyn FileSystem>,
+    ) -> Result<Option<TaskOutputs>> {
         if let Some(executor) = self.tasks.get(&task.name) {
             // Check task condition first
             if let Some(condition) = &task.condition {
                 use crate::expression::ExpressionEvaluator;
                 let evaluator = ExpressionEvaluator::new(model);
                 if !evaluator.evaluate_condition(condition)? {
-                    return Ok(());
+                    return Ok(None);
                 }
             }
 
@@ -158,11 +215,16 @@ impl TaskRegistry {
                 .get_project_directory()
                 .unwrap_or_else(|| PathBuf::from("."));
 
-            let context = TaskExecutionContext::new(evaluated_attributes, project_directory);
-            executor.execute(&context)
+            let context = TaskExecutionContext::new(evaluated_attributes, project_directory)
+                .with_location(task.location.clone())
+                .with_file_system(file_system.clone());
+            executor
+                .execute(&context)
+                .map(Some)
+                .map_err(|e| task.location.error(e))
         } else {
-            error!("Unknown task: {}", task.name);
-            Ok(()) // Don't fail on unknown tasks for now
+            error!("{}: error: Unknown task: {}", task.location, task.name);
+            Ok(None) // Don't fail on unknown tasks for now
         }
     }
 }
@@ -196,9 +258,11 @@ mod tests {
             name: "Message".to_string(),
             attributes,
             condition: None,
+            outputs: Vec::new(),
+            location: SourceLocation::default(),
         };
 
-        registry.execute_task(&task, &model)?;
+        registry.execute_task(&task, &model, &PhysicalFileSystem::shared())?;
 
         Ok(())
     }
@@ -20,7 +20,7 @@ mod integration_tests {
 
         let mut evaluator = ProjectEvaluator::new();
         evaluator.load_project(project_path)?;
-        evaluator.execute_target("Build")?;
+        evaluator.execute_target("Build").into_result()?;
 
         Ok(())
     }
@@ -36,7 +36,7 @@ mod integration_tests {
         evaluator.load_project(project_path)?;
 
         // Test with Debug configuration (should run tests)
-        evaluator.execute_target("Test")?;
+        evaluator.execute_target("Test").into_result()?;
 
         Ok(())
     }
@@ -65,19 +65,21 @@ mod integration_tests {
         let mut model = ProjectModel::new();
 
         // Add some items
-        use crate::object_model::Item;
+        use crate::object_model::{Item, SourceLocation};
         use std::collections::HashMap;
 
         let item1 = Item {
             item_type: "Source".to_string(),
             name: "file1.cs".to_string(),
             metadata: HashMap::new(),
+            location: SourceLocation::default(),
         };
 
         let item2 = Item {
             item_type: "Source".to_string(),
             name: "file2.cs".to_string(),
             metadata: HashMap::new(),
+            location: SourceLocation::default(),
         };
 
         model.add_item(item1);
@@ -123,7 +125,11 @@ mod integration_tests {
         let context =
             TaskExecutionContext::new(evaluated_attributes, temp_dir.path().to_path_buf());
         let copy_task = CopyTask;
-        copy_task.execute(&context)?;
+        let outputs = copy_task.execute(&context)?;
+        assert_eq!(
+            outputs.get("CopiedFiles"),
+            Some(&[test_file.to_string_lossy().into_owned()][..])
+        );
 
         // Verify the file was copied
         let copied_file = dest_dir.join("test.txt");
@@ -149,7 +155,7 @@ mod integration_tests {
 
         // The Build target depends on CopyResources, which depends on Compile, which depends on Clean
         // This should execute all targets in the correct order
-        evaluator.execute_target("Build")?;
+        evaluator.execute_target("Build").into_result()?;
 
         Ok(())
     }
@@ -0,0 +1,487 @@
+//! Schema validation of project files.
+//!
+//! Evaluation skips elements and attributes it does not understand, so a typo
+//! such as `<ItemGrop>` or `DependOnTargets=` would silently drop part of a build.
+//! This pass checks every MSBuild element against the names valid in its context
+//! and suggests the closest valid name.
+
+use anyhow::{Result, anyhow};
+use log::warn;
+use std::fmt;
+
+use crate::construction::{ProjectRootElement, XmlElement};
+use crate::object_model::SourceLocation;
+
+/// How schema violations are treated when a project is loaded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ValidationMode {
+    /// Report violations as warnings and keep going
+    #[default]
+    Lenient,
+    /// Report violations as errors and fail the load
+    Strict,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub location: SourceLocation,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {severity}: {}", self.location, self.message)
+    }
+}
+
+const PROJECT_ATTRIBUTES: &[&str] = &[
+    "DefaultTargets",
+    "InitialTargets",
+    "ToolsVersion",
+    "TreatAsLocalProperty",
+    "Sdk",
+];
+const PROJECT_CHILDREN: &[&str] = &[
+    "PropertyGroup",
+    "ItemGroup",
+    "ItemDefinitionGroup",
+    "Target",
+    "Import",
+    "ImportGroup",
+    "UsingTask",
+    "Choose",
+    "ProjectExtensions",
+    "Sdk",
+];
+const GROUP_ATTRIBUTES: &[&str] = &["Condition", "Label"];
+const PROPERTY_ATTRIBUTES: &[&str] = &["Condition"];
+/// Attributes with a meaning of their own on items; any other attribute is metadata
+pub const ITEM_ATTRIBUTES: &[&str] = &[
+    "Include",
+    "Exclude",
+    "Remove",
+    "Update",
+    "Condition",
+    "Label",
+    "KeepMetadata",
+    "RemoveMetadata",
+    "KeepDuplicates",
+    "MatchOnMetadata",
+    "MatchOnMetadataOptions",
+];
+const TARGET_ATTRIBUTES: &[&str] = &[
+    "Name",
+    "DependsOnTargets",
+    "Condition",
+    "Inputs",
+    "Outputs",
+    "Returns",
+    "BeforeTargets",
+    "AfterTargets",
+    "KeepDuplicateOutputs",
+    "Label",
+];
+const TARGET_CHILDREN: &[&str] = &["PropertyGroup", "ItemGroup", "OnError"];
+const TASK_ATTRIBUTES: &[&str] = &[
+    "Condition",
+    "ContinueOnError",
+    "MSBuildRuntime",
+    "MSBuildArchitecture",
+];
+const OUTPUT_ATTRIBUTES: &[&str] = &["TaskParameter", "PropertyName", "ItemName", "Condition"];
+const ON_ERROR_ATTRIBUTES: &[&str] = &["ExecuteTargets", "Condition"];
+const IMPORT_ATTRIBUTES: &[&str] = &[
+    "Project",
+    "Condition",
+    "Label",
+    "Sdk",
+    "Version",
+    "MinimumVersion",
+];
+const USING_TASK_ATTRIBUTES: &[&str] = &[
+    "TaskName",
+    "AssemblyName",
+    "AssemblyFile",
+    "TaskFactory",
+    "Condition",
+    "Architecture",
+    "Runtime",
+    "Override",
+];
+const CHOOSE_CHILDREN: &[&str] = &["When", "Otherwise"];
+const WHEN_CHILDREN: &[&str] = &["PropertyGroup", "ItemGroup", "Choose"];
+const SDK_ATTRIBUTES: &[&str] = &["Name", "Version", "MinimumVersion"];
+
+/// Check a project file against the MSBuild schema
+pub fn validate(root: &ProjectRootElement, mode: ValidationMode) -> Vec<Diagnostic> {
+    let mut validator = Validator {
+        severity: match mode {
+            ValidationMode::Lenient => Severity::Warning,
+            ValidationMode::Strict => Severity::Error,
+        },
+        diagnostics: Vec::new(),
+    };
+    validator.project(root.project());
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|d| (d.location.line, d.location.column));
+    diagnostics
+}
+
+/// Log the warnings for a loaded file and fail with its errors, if any
+pub fn enforce(root: &ProjectRootElement, mode: ValidationMode) -> Result<()> {
+    let mut errors = Vec::new();
+    for diagnostic in validate(root, mode) {
+        match diagnostic.severity {
+            Severity::Warning => warn!("{diagnostic}"),
+            Severity::Error => errors.push(diagnostic.to_string()),
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!(errors.join("\n")))
+    }
+}
+
+struct Validator {
+    severity: Severity,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator {
+    fn report(&mut self, location: &SourceLocation, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: self.severity,
+            location: location.clone(),
+            message,
+        });
+    }
+
+    /// Report a guess that a name was misspelled. The name may be intended, such
+    /// as metadata called `Removed`, so this is a warning in every mode.
+    fn suggest(&mut self, location: &SourceLocation, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            location: location.clone(),
+            message,
+        });
+    }
+
+    fn attributes(&mut self, element: &XmlElement, name: &str, allowed: &[&str]) {
+        for attribute in &element.attributes {
+            if attribute.is_namespace_declaration() || attribute.name.contains(':') {
+                continue;
+            }
+            if !allowed.contains(&attribute.name.as_str()) {
+                let message = format!(
+                    "The attribute \"{}\" is not valid on <{name}>.{}",
+                    attribute.name,
+                    did_you_mean(&attribute.name, allowed)
+                );
+                self.report(&element.location, message);
+            }
+        }
+    }
+
+    fn required(&mut self, element: &XmlElement, name: &str, attribute: &str) {
+        if element.attribute(attribute).is_none() {
+            let message = format!("<{name}> requires the \"{attribute}\" attribute.");
+            self.report(&element.location, message);
+        }
+    }
+
+    /// Report children of `parent` outside `allowed`, returning the valid ones
+    fn children<'a>(
+        &mut self,
+        parent: &'a XmlElement,
+        parent_name: &str,
+        allowed: &[&str],
+    ) -> Vec<(&'a str, &'a XmlElement)> {
+        let mut valid = Vec::new();
+        for (name, child) in parent.msbuild_children() {
+            if allowed.contains(&name) {
+                valid.push((name, child));
+            } else {
+                let message = format!(
+                    "The element <{name}> is not valid inside <{parent_name}>.{}",
+                    did_you_mean(name, allowed)
+                );
+                self.report(&child.location, message);
+            }
+        }
+        valid
+    }
+
+    fn project(&mut self, project: &XmlElement) {
+        self.attributes(project, "Project", PROJECT_ATTRIBUTES);
+        for (name, child) in self.children(project, "Project", PROJECT_CHILDREN) {
+            match name {
+                "PropertyGroup" => self.property_group(child),
+                "ItemGroup" => self.item_group(child, false),
+                "ItemDefinitionGroup" => self.item_definition_group(child),
+                "Target" => self.target(child),
+                "Import" => self.import(child),
+                "ImportGroup" => {
+                    self.attributes(child, name, GROUP_ATTRIBUTES);
+                    for (_, import) in self.children(child, name, &["Import"]) {
+                        self.import(import);
+                    }
+                }
+                "UsingTask" => {
+                    self.attributes(child, name, USING_TASK_ATTRIBUTES);
+                    self.required(child, name, "TaskName");
+                }
+                "Choose" => self.choose(child),
+                "Sdk" => {
+                    self.attributes(child, name, SDK_ATTRIBUTES);
+                    self.required(child, name, "Name");
+                }
+                // Free-form content for tools
+                _ => {}
+            }
+        }
+    }
+
+    fn property_group(&mut self, group: &XmlElement) {
+        self.attributes(group, "PropertyGroup", GROUP_ATTRIBUTES);
+        for (name, property) in group.msbuild_children() {
+            self.attributes(property, name, PROPERTY_ATTRIBUTES);
+        }
+    }
+
+    fn item_group(&mut self, group: &XmlElement, in_target: bool) {
+        self.attributes(group, "ItemGroup", GROUP_ATTRIBUTES);
+        for (name, item) in group.msbuild_children() {
+            // Other attributes are metadata, so only flag likely misspellings
+            for attribute in &item.attributes {
+                let attribute = attribute.name.as_str();
+                if !ITEM_ATTRIBUTES.contains(&attribute)
+                    && let Some(suggestion) = closest(attribute, ITEM_ATTRIBUTES)
+                {
+                    let message = format!(
+                        "The attribute \"{attribute}\" on <{name}> is treated as metadata. Did you mean \"{suggestion}\"?"
+                    );
+                    self.suggest(&item.location, message);
+                }
+            }
+            let has_operation = ["Include", "Remove", "Update"]
+                .iter()
+                .any(|operation| item.attribute(operation).is_some());
+            if !in_target && !has_operation {
+                let message = format!(
+                    "<{name}> requires an \"Include\", \"Remove\" or \"Update\" attribute."
+                );
+                self.report(&item.location, message);
+            }
+            self.metadata(item);
+        }
+    }
+
+    fn item_definition_group(&mut self, group: &XmlElement) {
+        self.attributes(group, "ItemDefinitionGroup", GROUP_ATTRIBUTES);
+        for (name, definition) in group.msbuild_children() {
+            self.attributes(definition, name, PROPERTY_ATTRIBUTES);
+            self.metadata(definition);
+        }
+    }
+
+    fn metadata(&mut self, item: &XmlElement) {
+        for (name, metadata) in item.msbuild_children() {
+            self.attributes(metadata, name, PROPERTY_ATTRIBUTES);
+        }
+    }
+
+    fn target(&mut self, target: &XmlElement) {
+        self.attributes(target, "Target", TARGET_ATTRIBUTES);
+        self.required(target, "Target", "Name");
+        for (name, child) in target.msbuild_children() {
+            match name {
+                "PropertyGroup" => self.property_group(child),
+                "ItemGroup" => self.item_group(child, true),
+                "OnError" => {
+                    self.attributes(child, name, ON_ERROR_ATTRIBUTES);
+                    self.required(child, name, "ExecuteTargets");
+                }
+                // Any other element is a task, whose parameters are its attributes
+                _ => {
+                    if let Some(suggestion) = closest(name, TARGET_CHILDREN) {
+                        let message = format!(
+                            "The element <{name}> is treated as a task. Did you mean <{suggestion}>?"
+                        );
+                        self.suggest(&child.location, message);
+                    }
+                    for attribute in &child.attributes {
+                        if let Some(suggestion) = closest(&attribute.name, TASK_ATTRIBUTES) {
+                            let message = format!(
+                                "The attribute \"{}\" on <{name}> is treated as a task parameter. Did you mean \"{suggestion}\"?",
+                                attribute.name
+                            );
+                            self.suggest(&child.location, message);
+                        }
+                    }
+                    for (_, output) in self.children(child, name, &["Output"]) {
+                        self.attributes(output, "Output", OUTPUT_ATTRIBUTES);
+                        self.required(output, "Output", "TaskParameter");
+                    }
+                }
+            }
+        }
+    }
+
+    fn import(&mut self, import: &XmlElement) {
+        self.attributes(import, "Import", IMPORT_ATTRIBUTES);
+        self.required(import, "Import", "Project");
+    }
+
+    fn choose(&mut self, choose: &XmlElement) {
+        self.attributes(choose, "Choose", &[]);
+        for (name, branch) in self.children(choose, "Choose", CHOOSE_CHILDREN) {
+            if name == "When" {
+                self.attributes(branch, name, &["Condition"]);
+                self.required(branch, name, "Condition");
+            } else {
+                self.attributes(branch, name, &[]);
+            }
+            for (child_name, child) in self.children(branch, name, WHEN_CHILDREN) {
+                match child_name {
+                    "PropertyGroup" => self.property_group(child),
+                    "ItemGroup" => self.item_group(child, false),
+                    _ => self.choose(child),
+                }
+            }
+        }
+    }
+}
+
+fn did_you_mean(name: &str, candidates: &[&str]) -> String {
+    match closest(name, candidates) {
+        Some(suggestion) => format!(" Did you mean \"{suggestion}\"?"),
+        None => String::new(),
+    }
+}
+
+/// The candidate most similar to `name`, if any is similar enough to be a typo
+fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let threshold = (name.len() / 3).max(1);
+    candidates
+        .iter()
+        .map(|&candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, candidate)| distance <= threshold && candidate != name)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Levenshtein distance, ignoring case
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_unknown_names_are_reported_with_suggestions() -> Result<()> {
+        let root = ProjectRootElement::parse(
+            r#"<Project DefaultTarget="Build">
+  <ItemGrop>
+    <Compile Include="a.cs" />
+  </ItemGrop>
+  <ItemGroup>
+    <Compile Inclde="b.cs" Link="b.cs" />
+  </ItemGroup>
+  <Target Name="Build" DependOnTargets="Restore">
+    <Message Text="hi" Condtion="true" />
+  </Target>
+  <Widget />
+</Project>"#,
+            PathBuf::from("test.proj"),
+        )?;
+
+        let messages: Vec<String> = validate(&root, ValidationMode::Lenient)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "test.proj(1,1): warning: The attribute \"DefaultTarget\" is not valid on <Project>. Did you mean \"DefaultTargets\"?",
+                "test.proj(2,3): warning: The element <ItemGrop> is not valid inside <Project>. Did you mean \"ItemGroup\"?",
+                "test.proj(6,5): warning: The attribute \"Inclde\" on <Compile> is treated as metadata. Did you mean \"Include\"?",
+                "test.proj(6,5): warning: <Compile> requires an \"Include\", \"Remove\" or \"Update\" attribute.",
+                "test.proj(8,3): warning: The attribute \"DependOnTargets\" is not valid on <Target>. Did you mean \"DependsOnTargets\"?",
+                "test.proj(9,5): warning: The attribute \"Condtion\" on <Message> is treated as a task parameter. Did you mean \"Condition\"?",
+                "test.proj(11,3): warning: The element <Widget> is not valid inside <Project>.",
+            ]
+        );
+
+        // Guesses about metadata and task parameters stay warnings when strict
+        let strict: Vec<Severity> = validate(&root, ValidationMode::Strict)
+            .iter()
+            .map(|d| d.severity)
+            .collect();
+        use Severity::{Error, Warning};
+        assert_eq!(
+            strict,
+            [Error, Error, Warning, Error, Error, Warning, Error]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_valid_project_has_no_diagnostics() -> Result<()> {
+        let root = ProjectRootElement::parse(
+            r#"<Project DefaultTargets="Build" xmlns="http://schemas.microsoft.com/developer/msbuild/2003">
+  <PropertyGroup Label="Globals"><Configuration Condition="'$(Configuration)' == ''">Debug</Configuration></PropertyGroup>
+  <ItemGroup><Compile Include="*.cs" Exclude="gen.cs"><Link>x</Link></Compile></ItemGroup>
+  <Choose><When Condition="true"><PropertyGroup><A>1</A></PropertyGroup></When><Otherwise /></Choose>
+  <Target Name="Build" Inputs="@(Compile)" Outputs="out.dll">
+    <Copy SourceFiles="@(Compile)" DestinationFolder="out"><Output TaskParameter="CopiedFiles" ItemName="Copied" /></Copy>
+    <OnError ExecuteTargets="Cleanup" />
+  </Target>
+  <ProjectExtensions><VisualStudio Anything="goes" /></ProjectExtensions>
+</Project>"#,
+            PathBuf::from("test.proj"),
+        )?;
+
+        assert_eq!(validate(&root, ValidationMode::Strict), []);
+
+        // Metadata and task parameters that look like misspellings only warn
+        let root = ProjectRootElement::parse(
+            r#"<Project>
+  <ItemGroup><Compile Include="a.cs" Removed="true" /></ItemGroup>
+  <Target Name="Build"><Check Conditions="x" /></Target>
+</Project>"#,
+            PathBuf::from("test.proj"),
+        )?;
+        assert_eq!(validate(&root, ValidationMode::Strict).len(), 2);
+        enforce(&root, ValidationMode::Strict)?;
+
+        Ok(())
+    }
+}